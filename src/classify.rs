@@ -0,0 +1,787 @@
+// Pane classification: decides whether each zellij child shell is idle or active
+// from the raw /proc data dumped by PROC_DUMP_SCRIPT.
+
+use regex::Regex;
+use std::collections::BTreeMap;
+
+/// The handful of /proc/<pid>/stat fields the classifier needs.
+#[derive(Debug, PartialEq)]
+pub struct ProcStat {
+    pub comm: String,
+    pub pgrp: i32,
+    pub tty_nr: i32,
+    pub tpgid: i32,
+}
+
+/// Parses a /proc/<pid>/stat line. The comm field is wrapped in parens and may itself
+/// contain spaces or parens, so it runs from the first `(` to the *last* `)`.
+pub fn parse_stat(line: &str) -> Option<ProcStat> {
+    let open = line.find('(')?;
+    let close = line.rfind(')')?;
+    if close < open {
+        return None;
+    }
+    let comm = line[open + 1..close].to_string();
+    // Fields after comm: state ppid pgrp session tty_nr tpgid ...
+    let rest: Vec<&str> = line[close + 1..].split_whitespace().collect();
+    if rest.len() < 6 {
+        return None;
+    }
+    Some(ProcStat {
+        comm,
+        pgrp: rest[2].parse().ok()?,
+        tty_nr: rest[4].parse().ok()?,
+        tpgid: rest[5].parse().ok()?,
+    })
+}
+
+/// What we know about the foreground process of a pane's terminal.
+#[derive(Debug, Default)]
+pub struct Foreground {
+    pub comm: String,
+    pub cmdline: String,
+    pub has_children: bool,
+    /// Whether it or any of its descendants is running (state R), and their CPU use
+    /// since the last poll in percent of one core, once there's a poll to compare with
+    /// (claude_working_cpu_threshold)
+    pub tree_running: bool,
+    pub tree_cpu_pct: Option<f64>,
+    /// The shell, its foreground process or one of that one's children was started with
+    /// ZELLIJ_IDLE_KEEPAWAKE set
+    pub keepawake: bool,
+    /// Swap files of an editor's buffers with unsaved changes (protect_editors)
+    pub dirty_swap_files: String,
+    /// Sleeping in a read of its terminal, per its state, wchan and stdin
+    /// (treat_stdin_wait_as_idle)
+    pub stdin_wait: bool,
+    /// When the foreground is a tmux/screen client or server, the stat line and
+    /// foreground of each pane inside the multiplexer's session
+    pub inner: Vec<(String, Foreground)>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PaneState {
+    Idle,
+    Active,
+}
+
+/// Options that shape classification, taken from the plugin config.
+pub struct ClassifyOptions<'a> {
+    pub claude_code_idle_detection: bool,
+    /// Above 0, an agent counts as working by what its process tree is doing rather than
+    /// by whether it has children: something in it running, or this much CPU used
+    pub claude_working_cpu_threshold: f64,
+    /// The CPU use of each pane's foreground tree, by shell pid (claude_working_cpu_threshold)
+    pub agent_cpu_pct: &'a BTreeMap<String, f64>,
+    /// Substrings that mark a foreground process as an agent CLI, matched against its
+    /// comm and cmdline. Empty means the built-in Claude Code check.
+    pub agent_process_patterns: &'a [String],
+    pub ignore_processes: &'a [String],
+    /// `re:` entries from ignore_processes, matched against the foreground comm and cmdline
+    pub ignore_patterns: &'a [Regex],
+    /// When non-empty, only these foreground processes count as active (and the
+    /// ignore lists are not consulted)
+    pub include_processes: &'a [String],
+    pub detect_ssh_sessions: bool,
+    /// A mosh session whose terminal has been left alone this long counts as gone;
+    /// 0 keeps every live mosh-server active
+    pub mosh_idle_after_secs: f64,
+    /// An ignored editor with unsaved changes counts as active (`editor-dirty`)
+    pub protect_editors: bool,
+    /// A foreground process with no children that's blocked reading its terminal is idle
+    /// (`stdin-wait`), e.g. a REPL at its prompt
+    pub treat_stdin_wait_as_idle: bool,
+    /// Zellij terminal pane ids whose shells are left out entirely (ignore_tabs / count_tabs)
+    pub excluded_panes: &'a [u32],
+}
+
+/// Classifies one zellij child from its stat line and foreground process info.
+/// Returns None for processes that aren't terminal panes (no controlling tty) or
+/// whose stat line can't be parsed. The detail string is the process name plus any
+/// annotation, e.g. `cargo`, `vim(ignored)`, `claude(claude-working)`.
+pub fn classify_pane(
+    stat_line: &str,
+    fg: &Foreground,
+    opts: &ClassifyOptions,
+) -> Option<(PaneState, String)> {
+    let stat = parse_stat(stat_line)?;
+    if stat.tty_nr == 0 {
+        return None;
+    }
+    let fg_comm = if fg.comm.is_empty() {
+        "unknown"
+    } else {
+        fg.comm.as_str()
+    };
+
+    // Whoever set it asked for this pane to keep the box up, whatever runs in it
+    if fg.keepawake {
+        return Some((PaneState::Active, format!("{}(env-keepawake)", fg_comm)));
+    }
+
+    // sshd as a pane's own process or its foreground means someone is connected through it
+    if opts.detect_ssh_sessions && (stat.comm == "sshd" || fg_comm == "sshd") {
+        return Some((PaneState::Active, "sshd(ssh-session)".to_string()));
+    }
+    if opts.detect_ssh_sessions && (stat.comm == "mosh-server" || fg_comm == "mosh-server") {
+        return Some((PaneState::Active, "mosh-server(mosh-session)".to_string()));
+    }
+
+    // A multiplexer just relays its inner panes, so judge it by them instead: active if
+    // any inner pane is, idle if all are. The dump only goes one level deep.
+    if is_multiplexer(fg_comm) {
+        let inner: Vec<(PaneState, String)> = fg
+            .inner
+            .iter()
+            .filter_map(|(stat_line, inner_fg)| classify_pane(stat_line, inner_fg, opts))
+            .collect();
+        if !inner.is_empty() {
+            return Some(
+                match inner
+                    .into_iter()
+                    .find(|(state, _)| *state == PaneState::Active)
+                {
+                    Some((_, detail)) => (PaneState::Active, format!("{}>{}", fg_comm, detail)),
+                    None => (PaneState::Idle, format!("{}(inner-idle)", fg_comm)),
+                },
+            );
+        }
+    }
+
+    if stat.pgrp == stat.tpgid {
+        return Some((PaneState::Idle, stat.comm));
+    }
+
+    if !opts.include_processes.is_empty() {
+        return Some(if opts.include_processes.iter().any(|p| p == fg_comm) {
+            (PaneState::Active, fg_comm.to_string())
+        } else {
+            (PaneState::Idle, format!("{}(not-included)", fg_comm))
+        });
+    }
+
+    // Literal names are checked before `re:` patterns, but a match on either ignores it.
+    let ignored = opts.ignore_processes.iter().any(|p| p == fg_comm)
+        || opts
+            .ignore_patterns
+            .iter()
+            .any(|re| re.is_match(fg_comm) || re.is_match(fg.cmdline.trim()));
+    if ignored {
+        if opts.protect_editors && !fg.dirty_swap_files.is_empty() {
+            return Some((PaneState::Active, format!("{}(editor-dirty)", fg_comm)));
+        }
+        return Some((PaneState::Idle, format!("{}(ignored)", fg_comm)));
+    }
+
+    // An agent CLI with children is running tools; without children it's at its prompt.
+    // That's only roughly so: helpers can outlive a task and a task can be all thinking,
+    // so claude_working_cpu_threshold goes by the process tree's activity instead.
+    if opts.claude_code_idle_detection
+        && is_agent(fg_comm, &fg.cmdline, opts.agent_process_patterns)
+    {
+        let label = if opts.agent_process_patterns.is_empty() {
+            "claude"
+        } else {
+            "agent"
+        };
+        let working = if opts.claude_working_cpu_threshold > 0.0 {
+            // Until there's an earlier poll to compare CPU with, children still decide
+            fg.tree_running
+                || fg
+                    .tree_cpu_pct
+                    .map_or(fg.has_children, |pct| pct >= opts.claude_working_cpu_threshold)
+        } else {
+            fg.has_children
+        };
+        return Some(if working {
+            (PaneState::Active, format!("{}({}-working)", fg_comm, label))
+        } else {
+            (PaneState::Idle, format!("{}({}-idle)", fg_comm, label))
+        });
+    }
+
+    if opts.treat_stdin_wait_as_idle && fg.stdin_wait && !fg.has_children {
+        return Some((PaneState::Idle, format!("{}(stdin-wait)", fg_comm)));
+    }
+
+    Some((PaneState::Active, fg_comm.to_string()))
+}
+
+/// Whether an `fgwait` value (`<state> <wchan> <stdin>`) is a process asleep reading its
+/// terminal. Kernels report the tty read as `n_tty_read`, or as `wait_woken` inside it.
+fn is_stdin_wait(value: &str) -> bool {
+    let mut fields = value.split_whitespace();
+    let (Some(state), Some(wchan), Some(stdin)) = (fields.next(), fields.next(), fields.next())
+    else {
+        return false;
+    };
+    state == "S"
+        && matches!(wchan, "n_tty_read" | "wait_woken")
+        && (stdin.starts_with("/dev/pts/") || stdin.starts_with("/dev/tty"))
+}
+
+/// Whether a foreground comm is a tmux or screen client or server.
+fn is_multiplexer(comm: &str) -> bool {
+    comm.starts_with("tmux") || comm == "screen"
+}
+
+/// Whether a process is an agent CLI: any configured pattern appears in its comm or
+/// cmdline, or with no patterns configured, it looks like Claude Code.
+fn is_agent(comm: &str, cmdline: &str, patterns: &[String]) -> bool {
+    if patterns.is_empty() {
+        return is_claude_code(comm, cmdline);
+    }
+    patterns
+        .iter()
+        .any(|p| comm.contains(p.as_str()) || cmdline.contains(p.as_str()))
+}
+
+/// Whether a process looks like it's running Claude Code, either as the `claude`
+/// binary or as `node` with a claude-code script on its command line.
+fn is_claude_code(comm: &str, cmdline: &str) -> bool {
+    if comm == "claude" {
+        return true;
+    }
+    comm == "node"
+        && (cmdline.contains("/@anthropic/claude-code/")
+            || cmdline.contains("/claude-code/")
+            || format!(" {} ", cmdline.trim()).contains(" claude "))
+}
+
+/// One zellij child as reported by PROC_DUMP_SCRIPT.
+#[derive(Debug, Default)]
+struct DumpRecord {
+    pid: String,
+    stat: String,
+    /// The zellij pane the shell runs in, from its ZELLIJ_PANE_ID
+    pane_id: Option<u32>,
+    fg: Foreground,
+}
+
+/// Turns PROC_DUMP_SCRIPT output into the `state:pid:detail` lines that
+/// parse_idle_check_output consumes. Box-wide signals use `-` as their pid.
+pub fn classify_dump(dump: &str, opts: &ClassifyOptions) -> String {
+    let mut records: Vec<DumpRecord> = Vec::new();
+    let mut out = String::new();
+    let mut mosh_sessions = 0;
+    for line in dump.lines() {
+        let parts: Vec<&str> = line.splitn(3, ':').collect();
+        if parts.len() < 3 {
+            continue;
+        }
+        let (key, pid, value) = (parts[0], parts[1], parts[2]);
+        if key == "sshsessions" {
+            let sessions: u32 = value.trim().parse().unwrap_or(0);
+            match sessions {
+                0 => {}
+                1 => out.push_str("active:-:ssh-session\n"),
+                n => out.push_str(&format!("active:-:ssh-session×{}\n", n)),
+            }
+            continue;
+        }
+        if key == "moshsession" {
+            // "-" is a session whose terminal couldn't be found; give it the benefit of the doubt
+            let left = value
+                .trim()
+                .parse::<f64>()
+                .is_ok_and(|idle| opts.mosh_idle_after_secs > 0.0 && idle >= opts.mosh_idle_after_secs);
+            if !left {
+                mosh_sessions += 1;
+            }
+            continue;
+        }
+        if key == "stat" {
+            records.push(DumpRecord {
+                pid: pid.to_string(),
+                stat: value.to_string(),
+                ..Default::default()
+            });
+            continue;
+        }
+        let Some(record) = records.last_mut().filter(|r| r.pid == pid) else {
+            continue;
+        };
+        if key == "paneid" {
+            record.pane_id = value.trim().parse().ok();
+            continue;
+        }
+        if key == "nstat" {
+            record
+                .fg
+                .inner
+                .push((value.to_string(), Foreground::default()));
+            continue;
+        }
+        // Inner-pane fields apply to the last inner pane, outer fields to the record itself
+        let (key, fg) = match key.strip_prefix('n') {
+            Some(inner_key) => match record.fg.inner.last_mut() {
+                Some((_, inner_fg)) => (inner_key, inner_fg),
+                None => continue,
+            },
+            None => (key, &mut record.fg),
+        };
+        match key {
+            "fgcomm" => fg.comm = value.trim().to_string(),
+            "fgcmd" => fg.cmdline = value.to_string(),
+            "fgkids" => fg.has_children = !value.trim().is_empty(),
+            "fgtree" => {
+                fg.tree_running = value
+                    .split_whitespace()
+                    .nth(1)
+                    .and_then(|running| running.parse::<u32>().ok())
+                    .is_some_and(|running| running > 0)
+            }
+            "fgdirty" => fg.dirty_swap_files = value.trim().to_string(),
+            "envkeepawake" => fg.keepawake = true,
+            "fgwait" => fg.stdin_wait = is_stdin_wait(value),
+            _ => {}
+        }
+    }
+    match mosh_sessions {
+        0 => {}
+        1 => out.push_str("active:-:mosh-session\n"),
+        n => out.push_str(&format!("active:-:mosh-session×{}\n", n)),
+    }
+
+    for record in &mut records {
+        record.fg.tree_cpu_pct = opts.agent_cpu_pct.get(&record.pid).copied();
+    }
+    for record in &records {
+        if record
+            .pane_id
+            .is_some_and(|id| opts.excluded_panes.contains(&id))
+        {
+            continue;
+        }
+        if let Some((state, detail)) = classify_pane(&record.stat, &record.fg, opts) {
+            let state = match state {
+                PaneState::Idle => "idle",
+                PaneState::Active => "active",
+            };
+            out.push_str(&format!("{}:{}:{}\n", state, record.pid, detail));
+        }
+    }
+    out
+}
+
+/// Splits the output of a custom idle_check_command into the `state:pid:detail` lines
+/// that parse_idle_check_output consumes and the lines that break that contract: the
+/// state has to be `idle` or `active` and the pid non-empty (`-` for a box-wide signal).
+pub fn check_custom_output(output: &str) -> (String, Vec<String>) {
+    let mut lines = String::new();
+    let mut malformed = Vec::new();
+    for line in output.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        match line.splitn(3, ':').collect::<Vec<_>>()[..] {
+            ["idle" | "active", pid, _] if !pid.trim().is_empty() => {
+                lines.push_str(line);
+                lines.push('\n');
+            }
+            _ => malformed.push(line.to_string()),
+        }
+    }
+    (lines, malformed)
+}
+
+/// Returns the `(shell pid, zellij pane id)` pairs in PROC_DUMP_SCRIPT output, for
+/// shells whose pane id could be read.
+pub fn dump_pane_ids(dump: &str) -> Vec<(String, u32)> {
+    dump.lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(3, ':');
+            if parts.next()? != "paneid" {
+                return None;
+            }
+            let pid = parts.next()?.to_string();
+            Some((pid, parts.next()?.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+/// Returns the value of a box-wide `key:-:value` line in PROC_DUMP_SCRIPT output.
+pub fn dump_value<'a>(dump: &'a str, key: &str) -> Option<&'a str> {
+    dump.lines().find_map(|line| {
+        let rest = line.strip_prefix(key)?.strip_prefix(":-:")?;
+        Some(rest.trim())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A shell on a terminal (tty_nr 34816) whose foreground is another process group
+    const BUSY: &str = "100 (bash) S 1 100 100 34816 200 4194560";
+    // The same shell at its prompt: it is the foreground process group itself
+    const PROMPT: &str = "100 (bash) S 1 100 100 34816 100 4194560";
+
+    static NO_CPU: BTreeMap<String, f64> = BTreeMap::new();
+
+    fn opts() -> ClassifyOptions<'static> {
+        ClassifyOptions {
+            claude_code_idle_detection: true,
+            claude_working_cpu_threshold: 0.0,
+            agent_cpu_pct: &NO_CPU,
+            agent_process_patterns: &[],
+            ignore_processes: &[],
+            ignore_patterns: &[],
+            include_processes: &[],
+            detect_ssh_sessions: true,
+            mosh_idle_after_secs: 0.0,
+            protect_editors: false,
+            treat_stdin_wait_as_idle: false,
+            excluded_panes: &[],
+        }
+    }
+
+    fn fg(comm: &str, cmdline: &str, has_children: bool) -> Foreground {
+        Foreground {
+            comm: comm.to_string(),
+            cmdline: cmdline.to_string(),
+            has_children,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parse_stat_reads_fields_after_comm() {
+        assert_eq!(
+            parse_stat(BUSY),
+            Some(ProcStat {
+                comm: "bash".to_string(),
+                pgrp: 100,
+                tty_nr: 34816,
+                tpgid: 200,
+            })
+        );
+        assert_eq!(
+            parse_stat("100 (tmux: server) S 1 100 100 0 -1").map(|s| s.comm),
+            Some("tmux: server".to_string())
+        );
+        assert_eq!(parse_stat("100 (bash) S 1 100"), None);
+        assert_eq!(parse_stat("garbage"), None);
+    }
+
+    #[test]
+    fn parse_stat_comm_with_parens_and_spaces() {
+        // comm runs to the last `)`, so a `)` inside it doesn't shift the fields
+        let stat = parse_stat("1234 (weird)name) S 1 1234 1234 34816 1234 4194560").unwrap();
+        assert_eq!(stat.comm, "weird)name");
+        assert_eq!((stat.pgrp, stat.tty_nr, stat.tpgid), (1234, 34816, 1234));
+
+        let stat = parse_stat("1234 (a (b) c) S 1 1234 1234 34816 99 0").unwrap();
+        assert_eq!(stat.comm, "a (b) c");
+        assert_eq!(stat.tpgid, 99);
+
+        assert_eq!(
+            classify_pane(
+                "1234 (weird)name) S 1 1234 1234 34816 1234 4194560",
+                &Foreground::default(),
+                &opts()
+            ),
+            Some((PaneState::Idle, "weird)name".to_string()))
+        );
+    }
+
+    #[test]
+    fn classify_pane_idle_and_active() {
+        assert_eq!(
+            classify_pane(PROMPT, &Foreground::default(), &opts()),
+            Some((PaneState::Idle, "bash".to_string()))
+        );
+        assert_eq!(
+            classify_pane(BUSY, &fg("cargo", "cargo build", true), &opts()),
+            Some((PaneState::Active, "cargo".to_string()))
+        );
+    }
+
+    #[test]
+    fn classify_pane_skips_processes_without_a_tty() {
+        let no_tty = "100 (bash) S 1 100 100 0 -1 4194560";
+        assert_eq!(
+            classify_pane(no_tty, &fg("cargo", "", false), &opts()),
+            None
+        );
+    }
+
+    #[test]
+    fn classify_pane_ignore_lists() {
+        let ignore = ["vim".to_string()];
+        let patterns = [Regex::new("^less ").unwrap()];
+        let opts = ClassifyOptions {
+            ignore_processes: &ignore,
+            ignore_patterns: &patterns,
+            ..opts()
+        };
+        assert_eq!(
+            classify_pane(BUSY, &fg("vim", "vim notes.md", false), &opts),
+            Some((PaneState::Idle, "vim(ignored)".to_string()))
+        );
+        assert_eq!(
+            classify_pane(BUSY, &fg("less", "less /var/log/syslog", false), &opts),
+            Some((PaneState::Idle, "less(ignored)".to_string()))
+        );
+        assert_eq!(
+            classify_pane(BUSY, &fg("make", "make", true), &opts),
+            Some((PaneState::Active, "make".to_string()))
+        );
+    }
+
+    #[test]
+    fn classify_pane_protects_dirty_editors() {
+        let ignore = ["vim".to_string()];
+        let opts = ClassifyOptions {
+            ignore_processes: &ignore,
+            protect_editors: true,
+            ..opts()
+        };
+        let clean = fg("vim", "vim notes.md", false);
+        let dirty = Foreground {
+            dirty_swap_files: ".notes.md.swp".to_string(),
+            ..fg("vim", "vim notes.md", false)
+        };
+        assert_eq!(
+            classify_pane(BUSY, &clean, &opts),
+            Some((PaneState::Idle, "vim(ignored)".to_string()))
+        );
+        assert_eq!(
+            classify_pane(BUSY, &dirty, &opts),
+            Some((PaneState::Active, "vim(editor-dirty)".to_string()))
+        );
+        let unprotected = ClassifyOptions {
+            protect_editors: false,
+            ..opts
+        };
+        assert_eq!(
+            classify_pane(BUSY, &dirty, &unprotected),
+            Some((PaneState::Idle, "vim(ignored)".to_string()))
+        );
+    }
+
+    #[test]
+    fn classify_pane_agent_working_and_idle() {
+        let node = "node /usr/lib/node_modules/@anthropic/claude-code/cli.js";
+        assert_eq!(
+            classify_pane(BUSY, &fg("node", node, true), &opts()),
+            Some((PaneState::Active, "node(claude-working)".to_string()))
+        );
+        assert_eq!(
+            classify_pane(BUSY, &fg("claude", "claude", false), &opts()),
+            Some((PaneState::Idle, "claude(claude-idle)".to_string()))
+        );
+
+        let patterns = ["aider".to_string()];
+        let opts = ClassifyOptions {
+            agent_process_patterns: &patterns,
+            ..opts()
+        };
+        assert_eq!(
+            classify_pane(BUSY, &fg("python3", "python3 -m aider", false), &opts),
+            Some((PaneState::Idle, "python3(agent-idle)".to_string()))
+        );
+        // Custom patterns replace the built-in check
+        assert_eq!(
+            classify_pane(BUSY, &fg("claude", "claude", false), &opts),
+            Some((PaneState::Active, "claude".to_string()))
+        );
+    }
+
+    #[test]
+    fn agent_working_goes_by_its_process_tree_with_a_cpu_threshold() {
+        let cpu = BTreeMap::from([("100".to_string(), 2.0), ("101".to_string(), 30.0)]);
+        let opts = ClassifyOptions {
+            claude_working_cpu_threshold: 10.0,
+            agent_cpu_pct: &cpu,
+            ..opts()
+        };
+        let dump = "\
+stat:100:100 (bash) S 1 100 100 34816 200 0
+fgcomm:100:claude
+fgkids:100:300
+fgtree:100:520 0
+stat:101:101 (bash) S 1 101 101 34817 201 0
+fgcomm:101:claude
+fgkids:101:
+fgtree:101:900 0
+stat:102:102 (bash) S 1 102 102 34818 202 0
+fgcomm:102:claude
+fgkids:102:
+fgtree:102:40 1
+stat:103:103 (bash) S 1 103 103 34819 203 0
+fgcomm:103:claude
+fgkids:103:303
+fgtree:103:40 0
+";
+        // Sleeping helpers at 2% are idle; 30% with no children, or anything running,
+        // is working; with no earlier poll to compare, children still decide
+        assert_eq!(
+            classify_dump(dump, &opts),
+            "idle:100:claude(claude-idle)\n\
+             active:101:claude(claude-working)\n\
+             active:102:claude(claude-working)\n\
+             active:103:claude(claude-working)\n"
+        );
+        let cpu = BTreeMap::from([("103".to_string(), 0.0)]);
+        let opts = ClassifyOptions {
+            agent_cpu_pct: &cpu,
+            ..opts
+        };
+        assert!(classify_dump(dump, &opts).contains("idle:103:claude(claude-idle)"));
+    }
+
+    #[test]
+    fn classify_dump_parses_records_and_inner_panes() {
+        let dump = "\
+sshsessions:-:2
+stat:100:100 (bash) S 1 100 100 34816 100 0
+stat:101:101 (bash) S 1 101 101 34817 300 0
+fgcomm:101:tmux: client
+fgcmd:101:tmux attach
+fgkids:101:
+nstat:101:400 (bash) S 1 400 400 34818 400 0
+nstat:101:401 (bash) S 1 401 401 34819 500 0
+nfgcomm:101:cargo
+nfgcmd:101:cargo test
+nfgkids:101:502
+stat:102:102 (bash) S 1 102 102 34820 600 0
+fgcomm:102:vim
+fgkids:999:should be dropped, wrong pid
+";
+        assert_eq!(
+            classify_dump(dump, &opts()),
+            "active:-:ssh-session×2\n\
+             idle:100:bash\n\
+             active:101:tmux: client>cargo\n\
+             active:102:vim\n"
+        );
+    }
+
+    #[test]
+    fn mosh_sessions_count_until_left_alone() {
+        let dump = "\
+sshsessions:-:0
+moshsession:-:30
+moshsession:-:-
+moshsession:-:7200
+stat:100:100 (bash) S 1 100 100 34816 100 0
+";
+        assert_eq!(
+            classify_dump(dump, &opts()),
+            "active:-:mosh-session×3\nidle:100:bash\n"
+        );
+        let opts = ClassifyOptions {
+            mosh_idle_after_secs: 3600.0,
+            ..opts()
+        };
+        assert_eq!(
+            classify_dump(dump, &opts),
+            "active:-:mosh-session×2\nidle:100:bash\n"
+        );
+        let dump = "moshsession:-:7200\n";
+        assert_eq!(classify_dump(dump, &opts), "");
+
+        let pane = Foreground {
+            comm: "mosh-server".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            classify_pane(BUSY, &pane, &opts),
+            Some((PaneState::Active, "mosh-server(mosh-session)".to_string()))
+        );
+    }
+
+    #[test]
+    fn custom_output_keeps_only_well_formed_lines() {
+        let (lines, malformed) = check_custom_output(
+            "active:42:lockfile\n\nidle:43:\nACTIVE:44:x\nbusy\nidle::no pid\nactive:-:backup-running\n",
+        );
+        assert_eq!(lines, "active:42:lockfile\nidle:43:\nactive:-:backup-running\n");
+        assert_eq!(malformed, ["ACTIVE:44:x", "busy", "idle::no pid"]);
+    }
+
+    #[test]
+    fn classify_dump_skips_excluded_panes() {
+        let dump = "\
+stat:100:100 (bash) S 1 100 100 34816 200 0
+paneid:100:3
+fgcomm:100:cargo
+stat:101:101 (bash) S 1 101 101 34817 101 0
+paneid:101:4
+stat:102:102 (bash) S 1 102 102 34818 102 0
+paneid:102:
+";
+        let opts = ClassifyOptions {
+            excluded_panes: &[3],
+            ..opts()
+        };
+        assert_eq!(classify_dump(dump, &opts), "idle:101:bash\nidle:102:bash\n");
+        assert_eq!(
+            dump_pane_ids(dump),
+            vec![("100".to_string(), 3), ("101".to_string(), 4)]
+        );
+    }
+
+    #[test]
+    fn classify_dump_skips_a_dump_script_that_got_past_its_own_pid_check() {
+        // zellij runs commands without a terminal, so the script's bash has tty_nr 0
+        let dump = "\
+stat:100:100 (bash) S 1234 100 100 34816 100 4194560
+fgcomm:100:bash
+stat:300:300 (bash) S 1234 300 300 0 -1 4194560
+fgcomm:300:
+";
+        assert_eq!(classify_dump(dump, &opts()), "idle:100:bash\n");
+    }
+
+    #[test]
+    fn env_keepawake_forces_a_pane_active() {
+        let dump = "\
+stat:100:100 (bash) S 1 100 100 34816 100 0
+fgcomm:100:bash
+envkeepawake:100:100
+stat:101:101 (bash) S 1 101 101 34817 200 0
+fgcomm:101:less
+envkeepawake:101:200
+stat:102:102 (bash) S 1 102 102 34818 102 0
+fgcomm:102:bash
+";
+        let ignore = ["less".to_string()];
+        let opts = ClassifyOptions {
+            ignore_processes: &ignore,
+            ..opts()
+        };
+        assert_eq!(
+            classify_dump(dump, &opts),
+            "active:100:bash(env-keepawake)\nactive:101:less(env-keepawake)\nidle:102:bash\n"
+        );
+    }
+
+    #[test]
+    fn stdin_wait_counts_as_idle_when_enabled() {
+        let dump = "\
+stat:100:100 (bash) S 1 100 100 34816 200 0
+fgcomm:100:python3
+fgkids:100:
+fgwait:100:S wait_woken /dev/pts/3
+stat:101:101 (bash) S 1 101 101 34817 300 0
+fgcomm:101:python3
+fgkids:101:
+fgwait:101:S 0 /dev/pts/4
+stat:102:102 (bash) S 1 102 102 34818 400 0
+fgcomm:102:cat
+fgkids:102:
+fgwait:102:S n_tty_read pipe:[123]
+";
+        assert_eq!(
+            classify_dump(dump, &opts()),
+            "active:100:python3\nactive:101:python3\nactive:102:cat\n"
+        );
+        let opts = ClassifyOptions {
+            treat_stdin_wait_as_idle: true,
+            ..opts()
+        };
+        assert_eq!(
+            classify_dump(dump, &opts),
+            "idle:100:python3(stdin-wait)\nactive:101:python3\nactive:102:cat\n"
+        );
+    }
+}
@@ -1,197 +1,1486 @@
-use std::collections::BTreeMap;
+mod classify;
+mod persist;
+mod savings;
+mod schedule;
+
+use classify::ClassifyOptions;
+use persist::PersistedState;
+use savings::Savings;
+use regex::Regex;
+use schedule::TimeoutRule;
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{SystemTime, UNIX_EPOCH};
 use zellij_tile::prelude::*;
+// Tests run without the zellij host: these stand-ins record commands instead of running them
+#[cfg(test)]
+use tests::{run_command, set_timeout};
 
-const POLL_INTERVAL_SECS: f64 = 5.0;
+const DEFAULT_POLL_INTERVAL_SECS: f64 = 5.0;
 const DEFAULT_IDLE_TIMEOUT_SECS: f64 = 300.0;
+const DEFAULT_MAX_POLL_INTERVAL_SECS: f64 = 60.0;
+const DEFAULT_POLL_BACKOFF_AFTER_SECS: f64 = 600.0;
 const DEFAULT_COUNTDOWN_SECS: f64 = 60.0;
 const DEFAULT_SUSPEND_ACTION: &str = "suspend";
+// Every suspend_action the built-in scripts know
+const SUSPEND_ACTIONS: &[&str] = &[
+    "suspend",
+    "stop",
+    "ec2-stop",
+    "ec2-hibernate",
+    "azure-deallocate",
+    "azure-stop",
+    "shutdown",
+    "systemd-suspend",
+    "auto",
+    "lock",
+    "none",
+];
+const DEFAULT_FALLBACK_ACTION: &str = "log";
+const DEFAULT_SNOOZE_SECS: f64 = 1800.0;
+const DEFAULT_EXTEND_SECS: f64 = 300.0;
+const DEFAULT_SUSPEND_MAX_RETRIES: u32 = 3;
+const DEFAULT_RETRY_BACKOFF_SECS: f64 = 30.0;
+const DEFAULT_SUSPEND_TIMEOUT_SECS: f64 = 300.0;
+const DEFAULT_LOG_MAX_BYTES: u64 = 1024 * 1024;
+const DEFAULT_SAFETY_POLL_SECS: f64 = 60.0;
+const DEFAULT_GPU_ACTIVE_THRESHOLD_PCT: f64 = 10.0;
+const DEFAULT_NETWORK_ACTIVE_KBPS: f64 = 1000.0;
+const DEFAULT_CONTAINER_CPU_THRESHOLD_PCT: f64 = 5.0;
+const DEFAULT_CHILD_PID_REFRESH_POLLS: u64 = 12;
+const DEFAULT_COLOR_IDLE: &str = "32";
+const DEFAULT_COLOR_COUNTDOWN: &str = "43;30;1";
+const DEFAULT_COLOR_WARNING: &str = "101;30;1";
+const DEFAULT_COLOR_SUSPEND: &str = "41;97;1";
+const DEFAULT_COLOR_SNOOZE: &str = "36";
+const DEFAULT_COLOR_ACTIVE: &str = "34";
+
+// A wall-clock gap between timer ticks larger than this many poll intervals means
+// the machine was suspended (or the plugin otherwise stalled) and has since resumed.
+const RESUME_GAP_POLL_MULTIPLE: f64 = 6.0;
+
+// A timer this much earlier than next_tick_unix is a stale one, superseded by a newer timer
+const TIMER_SLACK_SECS: f64 = 0.5;
+
+// An idle check whose result hasn't arrived after this many polls is assumed lost (e.g.
+// run before RunCommands was granted), so the next poll starts a new one
+const IDLE_CHECK_STALE_POLLS: u64 = 3;
+
+// Input within this long of the last handled input, with nothing idle to reset, is skipped
+const INPUT_BURST_SECS: f64 = 1.0;
+
+// Input this long before a zellij-idle-confirm still leaves it to confirm, since the
+// keybinding that sends it is input too
+const CONFIRM_GRACE_SECS: f64 = 1.0;
+
+// How long the bar shows `+5m` after a zellij-idle-extend
+const EXTEND_FLASH_SECS: f64 = 3.0;
+
+// Below this many columns the bar shows a one-character glyph for the state instead of text
+const NARROW_STATUS_COLS: usize = 4;
+
+// show_sparkline draws this many of the latest polls, and only leaves the rest of the bar
+// when that's at least SPARKLINE_MIN_TEXT_COLS wide
+const SPARKLINE_POLLS: usize = 12;
+const SPARKLINE_MIN_TEXT_COLS: usize = 12;
+
+// Config errors take over the status bar for this many renders after loading
+const CONFIG_ERR_RENDERS: u32 = 20;
+
+// This many consecutive idle checks finding no panes at all means detection is broken
+const NO_PANES_POLLS: u32 = 3;
 
 // Bash script to flush log lines to a persistent file.
 // $1 = log content (newline-separated lines)
-// Prepends a timestamp to each line.
+// $2 = log file path (empty = ~/.local/share/zellij-idle/zellij-idle.log; a leading ~ is expanded)
+// $3 = max log size in bytes; a larger file is rotated to <path>.1 before appending
+// $4 = log_format; text lines get a timestamp prepended, json lines carry their own `ts`
 const LOG_FLUSH_SCRIPT: &str = r#"
-dir="$HOME/.local/share/zellij-idle"
-mkdir -p "$dir"
-ts=$(date '+%Y-%m-%d %H:%M:%S')
-printf '%s\n' "$1" | sed "s/^/$ts /" >> "$dir/zellij-idle.log"
+file="${2:-$HOME/.local/share/zellij-idle/zellij-idle.log}"
+file="${file/#\~/$HOME}"
+mkdir -p "$(dirname "$file")"
+if [ -f "$file" ] && [ "$(wc -c < "$file")" -ge "$3" ]; then
+  mv -f "$file" "$file.1"
+fi
+if [ "$4" = json ]; then
+  printf '%s\n' "$1" >> "$file"
+else
+  ts=$(date '+%Y-%m-%d %H:%M:%S')
+  printf '%s\n' "$1" | sed "s/^/$ts /" >> "$file"
+fi
+"#;
+
+// Bash script that saves the session layout before a suspend (dump_layout_before_suspend),
+// so a stopped box's session can be recreated with `zellij --layout <file>`.
+// It goes through a temp file, so a failed dump leaves the previous one in place.
+// $1 = layout_dump_path (empty = ~/.local/share/zellij-idle/layout.kdl; a leading ~ is expanded)
+// $2 = session name (empty = whichever session `zellij action` picks)
+// Prints the path written to.
+const DUMP_LAYOUT_SCRIPT: &str = r#"
+file="${1:-$HOME/.local/share/zellij-idle/layout.kdl}"
+file="${file/#\~/$HOME}"
+mkdir -p "$(dirname "$file")"
+if [ -n "$2" ]; then
+  zellij --session "$2" action dump-layout > "$file.tmp"
+else
+  zellij action dump-layout > "$file.tmp"
+fi || { rm -f "$file.tmp"; exit 1; }
+mv -f "$file.tmp" "$file"
+echo "$file"
+"#;
+
+// Prints the path and modification time of the layout DUMP_LAYOUT_SCRIPT saved, if any.
+// $1 = layout_dump_path, as for DUMP_LAYOUT_SCRIPT
+const FIND_LAYOUT_DUMP_SCRIPT: &str = r#"
+file="${1:-$HOME/.local/share/zellij-idle/layout.kdl}"
+file="${file/#\~/$HOME}"
+[ -s "$file" ] || exit 0
+echo "$file (saved $(date -r "$file" '+%Y-%m-%d %H:%M:%S'))"
 "#;
 
-// Inline bash script for idle detection.
-// Finds direct children of zellij, checks /proc/<pid>/stat to determine
-// if the shell is the foreground process (idle) or something else is running (active).
-// Skips processes without a controlling terminal (tty_nr == 0).
+// Inline bash script that dumps the /proc data needed for idle detection.
+// The idle/active decision itself is made in Rust by classify::classify_dump.
 //
-// Arguments:
-//   $1 = zellij PID
-//   $2 = claude_code_idle_detection ("true" or "false")
-//   $3 = ignore_processes (comma-separated list, e.g. "vim,nvim,less")
+// For each direct child of zellij (one per terminal pane), emits:
+//   stat:<pid>:<contents of /proc/<pid>/stat>
+//   fgcomm:<pid>:<comm of the terminal's foreground process group leader>
+//   fgcmd:<pid>:<space-separated cmdline of that process>
+//   fgkids:<pid>:<child PIDs of that process, if any>
+// and, when the shell, that process or one of its children was started with
+// ZELLIJ_IDLE_KEEPAWAKE set to anything but empty or 0,
+//   envkeepawake:<pid>:<PID of the first of those that was>
+//
+// When $2 is "true", also emits
+//   sshsessions:-:<number of live inbound sshd sessions>
+// counting the per-connection `sshd: user@pts/N` (or `@notty`) processes; OpenSSH
+// 9.8+ names these `sshd-session`. Sessions with a zellij client among their
+// descendants are left out: that's the user attached to this very session, whose
+// keypresses already count, and counting it would keep the box awake for good.
+// Likewise, for each mosh-server outside those connections:
+//   moshsession:-:<seconds since its terminal was last read, or "-" if unknown>
+// mosh-server outlives a roaming client, so the terminal's access time is the only
+// sign of whether anyone is still typing into it.
+//
+// When $3 is "true", also emits
+//   cputicks:-:<utime+stime+cutime+cstime summed over all descendants of zellij>
+//   clktck:-:<clock ticks per second>
+// so the plugin can compute CPU usage from the delta between two polls.
+//
+// When $5 is "true", also emits
+//   gpuutil:-:<comma-separated utilization % per GPU, or "unavailable">
+// from nvidia-smi.
+//
+// When $6 is "true", also emits
+//   netbytes:-:<RX+TX bytes summed over all interfaces but lo, from /proc/net/dev>
+// so the plugin can compute throughput from the delta between two polls.
+//
+// When $7 is "true" and a pane's foreground is vi/vim/nvim, also emits
+//   fgdirty:<pid>:<names of swap files the editor (or its --embed child) has open and
+//                  marked modified>
+// The modified flag is the byte at offset 1007 of a Vim swap file's block 0 (b0_dirty),
+// 'U' while the buffer has unsaved changes.
+//
+// When $12 is "true", also emits how long since the last keyboard or mouse input to the
+// machine itself, for respect_system_idle: the least of what xprintidle (X11) and logind's
+// idle hints for graphical sessions report. A session whose IdleSinceHint is 0 has no
+// idle daemon setting the hint, so its IdleHint=no means nothing and it's skipped:
+//   sysidle:-:<seconds, or "unknown" when none of them answers>
+//
+// When $14 is "true", also emits for each pane's foreground process, for
+// claude_working_cpu_threshold:
+//   fgtree:<pid>:<utime+stime summed over it and its descendants> <how many of them are
+//                running (state R)>
+//   clktck:-:<clock ticks per second>
+//
+// When $13 is a number and there are more pane shells than that, emits only
+//   toomanypanes:-:<number of pane shells>
+// and nothing after it, since scanning that many would make the check itself a load.
+//
+// Also emits the pane shells' PIDs, which it reads from KIDS like everything else:
+//   children:-:<space-separated PIDs>
+//
+// When $10 is set, also emits the CPU usage of each running docker or podman container:
+//   containercpu:-:<engine>/<container name> <CPUPerc, e.g. 12.34%>
+// or, when an engine's stats fail or take over 3s (which holds up the whole check),
+//   containererror:-:<engine> <exit status, 124 = timed out>
+// $10 is the engines' paths found by an earlier dump, or "probe" to look them up, in which
+// case the dump also emits them for the plugin to pass next time:
+//   containerengines:-:<space-separated paths, or "none">
+//
+// When $9 is "true", also emits how each pane's foreground process is waiting, for
+// treat_stdin_wait_as_idle:
+//   fgwait:<pid>:<state from its stat> <its wchan> <where its stdin points>
+//
+// When $8 is "true", also emits where the box draws power from, for battery_only:
+//   power:-:<"ac", "battery", or "unknown" when no AC adapter or discharging battery shows up>
+//
+// When $4 is "true" and a pane's foreground is tmux or screen, also emits the
+// children of the multiplexer's server (its inner panes) after that pane's lines:
+//   nstat:<pid>:<contents of /proc/<inner pid>/stat>
+//   nfgcomm:<pid>:..., nfgcmd:<pid>:..., nfgkids:<pid>:...  (as above, for the inner pane)
+// A pane running `tmux: server` directly uses its own children; otherwise every tmux
+// (or SCREEN) server on the box is included, since the client's server isn't its child.
 //
-// Claude Code detection: When a foreground process is "claude" or "node" running
-// Claude Code, we check if that process has children. If it does, Claude Code is
-// actively working (running tools, generating code). If not, it's idle at its prompt.
+// Should zellij run the dump as a child of its own, it would find itself among the panes,
+// so it leaves out its own PID. With no tty, it wouldn't count as a pane anyway.
 //
-// ignore_processes: Any foreground process whose name matches this list is treated
-// as idle, allowing suspend even when those processes are running.
-const IDLE_CHECK_SCRIPT: &str = r#"
+// Arguments:
+//   $1 = zellij PID
+//   $2 = detect_ssh_sessions ("true" or "false")
+//   $3 = sample CPU usage ("true" or "false")
+//   $4 = recurse_multiplexers ("true" or "false")
+//   $5 = detect_gpu ("true" or "false")
+//   $6 = detect_network_active ("true" or "false")
+//   $7 = protect_editors ("true" or "false")
+//   $8 = battery_only ("true" or "false")
+//   $9 = treat_stdin_wait_as_idle ("true" or "false")
+//   $10 = container engines for detect_containers ("" = off, "probe" or a list of paths)
+//   $11 = unused here (MACOS_PROC_DUMP_SCRIPT's cached pane shell PIDs)
+//   $12 = respect_system_idle ("true" or "false")
+//   $13 = max_panes_for_detection ("" = no limit)
+//   $14 = sample the foreground process trees ("true" or "false")
+const PROC_DUMP_SCRIPT: &str = r#"
 ZELLIJ_PID="$1"
-CLAUDE_DETECT="$2"
-IGNORE_PROCS="$3"
-
-# Build an associative array of ignored process names for fast lookup
-declare -A IGNORED
-if [ -n "$IGNORE_PROCS" ]; then
-  IFS=',' read -ra IGNORE_ARR <<< "$IGNORE_PROCS"
-  for p in "${IGNORE_ARR[@]}"; do
-    p="$(echo "$p" | tr -d ' ')"
-    [ -n "$p" ] && IGNORED["$p"]=1
+DETECT_SSH="$2"
+SAMPLE_CPU="$3"
+RECURSE_MUX="$4"
+DETECT_GPU="$5"
+DETECT_NET="$6"
+PROTECT_EDITORS="$7"
+BATTERY_ONLY="$8"
+STDIN_WAIT="$9"
+CONTAINER_ENGINES="${10}"
+SYSTEM_IDLE="${12}"
+MAX_PANES="${13}"
+FG_TREES="${14}"
+
+# Every process's stat line, read in one go. Everything below looks processes up in
+# these instead of reading /proc/<pid>/stat or running pgrep per PID, since each of
+# those is a fork: STAT maps a PID to its stat line, KIDS to its children's PIDs.
+declare -A STAT KIDS
+while read -r line; do
+  pid=${line%% *}
+  f=(${line##*)})
+  STAT[$pid]=$line
+  KIDS[${f[1]}]+=" $pid"
+done < <(cat /proc/[0-9]*/stat 2>/dev/null)
+
+# Sets COMM to a process's comm, the part of its stat line in parentheses
+comm_of() {
+  if [ -n "${STAT[$1]}" ]; then
+    COMM=${STAT[$1]#*(}
+    COMM=${COMM%)*}
+  else
+    COMM=unknown
+  fi
+}
+# Sets CMDLINE to a process's cmdline with each NUL (and newline) turned into a space
+cmdline_of() {
+  local args=()
+  CMDLINE=""
+  mapfile -d '' -t args 2>/dev/null < "/proc/$1/cmdline"
+  [ ${#args[@]} -gt 0 ] && printf -v CMDLINE '%s ' "${args[@]}"
+  CMDLINE=${CMDLINE//$'\n'/ }
+}
+# Whether the environment variables given, one per argument, set ZELLIJ_IDLE_KEEPAWAKE
+# to anything but empty or 0
+keepawake_in() {
+  local var
+  for var in "$@"; do
+    case "$var" in
+      ZELLIJ_IDLE_KEEPAWAKE=|ZELLIJ_IDLE_KEEPAWAKE=0) ;;
+      ZELLIJ_IDLE_KEEPAWAKE=*) return 0 ;;
+    esac
+  done
+  return 1
+}
+# Sets NAMED to the PIDs whose comm is exactly $1, like pgrep -x
+named() {
+  NAMED=""
+  for pid in "${!STAT[@]}"; do
+    comm_of "$pid"
+    [ "$COMM" = "$1" ] && NAMED="$NAMED $pid"
   done
+}
+
+[ "$FG_TREES" = "true" ] && echo "clktck:-:$(getconf CLK_TCK 2>/dev/null || echo 100)"
+
+# Less this script's own bash, when zellij runs it as a child of its own
+CHILDREN=""
+for kid in ${KIDS[$ZELLIJ_PID]}; do
+  [ "$kid" = "$$" ] || CHILDREN="$CHILDREN $kid"
+done
+CHILDREN=${CHILDREN# }
+echo "children:-:$CHILDREN"
+
+set -- $CHILDREN
+if [ -n "$MAX_PANES" ] && [ $# -gt "$MAX_PANES" ]; then
+  echo "toomanypanes:-:$#"
+  exit 0
 fi
 
-# Check if a PID looks like it's running Claude Code.
-is_claude_code() {
-  local pid="$1"
-  local comm="$2"
-  if [ "$comm" = "claude" ]; then
-    return 0
+if [ "$SYSTEM_IDLE" = "true" ]; then
+  now=$(date +%s)
+  idle=""
+  least() { [ -z "$idle" ] || [ "$1" -lt "$idle" ] && idle=$1; }
+  if ms=$(DISPLAY="${DISPLAY:-:0}" timeout 2 xprintidle 2>/dev/null); then
+    least $((ms / 1000))
   fi
-  if [ "$comm" = "node" ]; then
-    local cmdline
-    cmdline=$(tr '\0' ' ' < /proc/$pid/cmdline 2>/dev/null) || return 1
-    case "$cmdline" in
-      */@anthropic/claude-code/* | */claude-code/* | *" claude "*) return 0 ;;
+  for session in $(loginctl list-sessions --no-legend 2>/dev/null | awk '{print $1}'); do
+    info=$(loginctl show-session "$session" -p Type -p IdleHint -p IdleSinceHint 2>/dev/null)
+    case "$info" in *Type=x11*|*Type=wayland*|*Type=mir*) ;; *) continue ;; esac
+    since=$(sed -n 's/^IdleSinceHint=//p' <<< "$info")
+    [ "${since:-0}" -gt 0 ] || continue
+    case "$info" in
+      *IdleHint=no*) least 0 ;;
+      *) least $((now - since / 1000000)) ;;
     esac
+  done
+  echo "sysidle:-:${idle:-unknown}"
+fi
+
+if [ "$CONTAINER_ENGINES" = "probe" ]; then
+  CONTAINER_ENGINES=$(echo $(command -v docker podman 2>/dev/null))
+  echo "containerengines:-:${CONTAINER_ENGINES:-none}"
+fi
+for engine in $CONTAINER_ENGINES; do
+  [ "$engine" = none ] && continue
+  stats=$(timeout 3 "$engine" stats --no-stream --format '{{.Name}} {{.CPUPerc}}' 2>/dev/null)
+  status=$?
+  if [ "$status" -ne 0 ]; then
+    echo "containererror:-:${engine##*/} $status"
+    continue
   fi
-  return 1
-}
+  while read -r name cpu; do
+    [ -n "$name" ] && echo "containercpu:-:${engine##*/}/$name $cpu"
+  done <<< "$stats"
+done
 
-# Check if a process has any child processes
-has_children() {
-  local pid="$1"
-  local children
-  if [ -f "/proc/$pid/task/$pid/children" ]; then
-    children=$(cat /proc/$pid/task/$pid/children 2>/dev/null)
+if [ "$BATTERY_ONLY" = "true" ]; then
+  power=unknown
+  for supply in /sys/class/power_supply/*; do
+    case "$(cat "$supply/type" 2>/dev/null)" in
+      Mains|USB) [ "$(cat "$supply/online" 2>/dev/null)" = 1 ] && { power=ac; break; } ;;
+      Battery) [ "$(cat "$supply/status" 2>/dev/null)" = Discharging ] && power=battery ;;
+    esac
+  done
+  echo "power:-:$power"
+fi
+
+if [ "$SAMPLE_CPU" = "true" ]; then
+  total=0
+  queue=${KIDS[$ZELLIJ_PID]}
+  while [ -n "$queue" ]; do
+    next=""
+    for pid in $queue; do
+      # Fields after comm: utime, stime, cutime and cstime are the 12th to 15th
+      f=(${STAT[$pid]##*)})
+      total=$((total + f[11] + f[12] + f[13] + f[14]))
+      next="$next${KIDS[$pid]}"
+    done
+    queue=$next
+  done
+  echo "cputicks:-:$total"
+  echo "clktck:-:$(getconf CLK_TCK 2>/dev/null || echo 100)"
+fi
+
+if [ "$DETECT_GPU" = "true" ]; then
+  if util=$(nvidia-smi --query-gpu=utilization.gpu --format=csv,noheader,nounits 2>/dev/null); then
+    echo "gpuutil:-:$(echo $util | tr ' ' ',')"
   else
-    children=$(pgrep -P "$pid" 2>/dev/null)
+    echo "gpuutil:-:unavailable"
   fi
-  [ -n "$(echo "$children" | tr -d '[:space:]')" ]
-}
-
-for child in $(pgrep -P "$ZELLIJ_PID"); do
-  stat=$(cat /proc/$child/stat 2>/dev/null) || continue
-  comm="${stat#*(}"
-  comm="${comm%)*}"
-  rest="${stat##*) }"
-  tty_nr=$(echo "$rest" | awk '{print $5}')
-  [ "$tty_nr" = "0" ] && continue
-  pgrp=$(echo "$rest" | awk '{print $3}')
-  tpgid=$(echo "$rest" | awk '{print $6}')
-  if [ "$pgrp" = "$tpgid" ]; then
-    echo "idle:$child:$comm"
-  else
-    fg_pid="$tpgid"
-    fg_comm=$(cat /proc/$fg_pid/comm 2>/dev/null || echo "unknown")
-
-    # Check ignore_processes list
-    if [ -n "${IGNORED[$fg_comm]+x}" ]; then
-      echo "idle:$child:$fg_comm(ignored)"
-      continue
-    fi
-
-    # Check Claude Code idle detection
-    if [ "$CLAUDE_DETECT" = "true" ] && is_claude_code "$fg_pid" "$fg_comm"; then
-      if has_children "$fg_pid"; then
-        echo "active:$child:$fg_comm(claude-working)"
-      else
-        echo "idle:$child:$fg_comm(claude-idle)"
-      fi
-      continue
-    fi
-
-    echo "active:$child:$fg_comm"
+fi
+
+if [ "$DETECT_NET" = "true" ]; then
+  # Interface lines look like "  eth0: <8 rx fields> <8 tx fields>"; bytes are the first of each
+  echo "netbytes:-:$(awk 'NR > 2 { sub(/^ +/, ""); split($0, f, /[: ]+/); if (f[1] != "lo") total += f[2] + f[10] } END { printf "%.0f", total }' /proc/net/dev 2>/dev/null)"
+fi
+
+if [ "$DETECT_SSH" = "true" ]; then
+  # Ancestors of every zellij client, i.e. the connections people are attached through
+  attached=" "
+  named zellij
+  for client in $NAMED; do
+    [ "$client" = "$ZELLIJ_PID" ] && continue
+    p=$client
+    while [ "$p" -gt 1 ] 2>/dev/null; do
+      attached="$attached$p "
+      [ -n "${STAT[$p]}" ] || break
+      f=(${STAT[$p]##*)})
+      p=${f[1]}
+    done
+  done
+  sessions=0
+  for sshd in $(pgrep -f '^sshd(-session)?: [^ ]+@' 2>/dev/null); do
+    case "$attached" in *" $sshd "*) ;; *) sessions=$((sessions + 1)) ;; esac
+  done
+  echo "sshsessions:-:$sessions"
+  named mosh-server
+  for mosh in $NAMED; do
+    case "$attached" in *" $mosh "*) continue ;; esac
+    idle=-
+    for kid in ${KIDS[$mosh]}; do
+      tty=$(readlink /proc/$kid/fd/0 2>/dev/null)
+      case "$tty" in /dev/pts/*) atime=$(stat -c %X "$tty" 2>/dev/null) && idle=$(( $(date +%s) - atime )) ;; esac
+    done
+    echo "moshsession:-:$idle"
+  done
+fi
+
+for child in $CHILDREN; do
+  stat=${STAT[$child]}
+  [ -n "$stat" ] || continue
+  # comm is "(...)" and may itself contain ')', so the fields start after the *last* ')'
+  # (see proc(5)). The greedy ## strips everything up to and including it.
+  f=(${stat##*)})
+  [ "${f[1]}" = "$ZELLIJ_PID" ] || continue
+  [ "$child" = "$$" ] && continue
+  echo "stat:$child:$stat"
+  # zellij sets ZELLIJ_PANE_ID in each pane's environment; it ties the shell to its tab
+  env=()
+  mapfile -d '' -t env 2>/dev/null < /proc/$child/environ
+  pane_id=""
+  for var in "${env[@]}"; do
+    case "$var" in ZELLIJ_PANE_ID=*) pane_id=${var#*=} ;; esac
+  done
+  echo "paneid:$child:$pane_id"
+  # A process's environ is fixed when it starts, so a variable exported in the shell
+  # shows up in what it runs next. One we can't read (another user's) just doesn't count.
+  keepawake=""
+  keepawake_in "${env[@]}" && keepawake=$child
+  tpgid=${f[5]}
+  [ -n "$tpgid" ] && [ "$tpgid" -gt 0 ] 2>/dev/null || continue
+  comm_of "$tpgid"
+  fg_comm=$COMM
+  echo "fgcomm:$child:$fg_comm"
+  cmdline_of "$tpgid"
+  echo "fgcmd:$child:$CMDLINE"
+  kids=${KIDS[$tpgid]# }
+  echo "fgkids:$child:$kids"
+  for p in $tpgid $kids; do
+    [ -z "$keepawake" ] || break
+    env=()
+    mapfile -d '' -t env 2>/dev/null < /proc/$p/environ
+    keepawake_in "${env[@]}" && keepawake=$p
+  done
+  [ -n "$keepawake" ] && echo "envkeepawake:$child:$keepawake"
+
+  if [ "$FG_TREES" = "true" ]; then
+    ticks=0
+    running=0
+    queue=$tpgid
+    while [ -n "$queue" ]; do
+      next=""
+      for pid in $queue; do
+        [ -n "${STAT[$pid]}" ] || continue
+        f=(${STAT[$pid]##*)})
+        ticks=$((ticks + f[11] + f[12]))
+        [ "${f[0]}" = R ] && running=$((running + 1))
+        next="$next${KIDS[$pid]}"
+      done
+      queue=$next
+    done
+    echo "fgtree:$child:$ticks $running"
+  fi
+
+  if [ "$STDIN_WAIT" = "true" ]; then
+    f=(${STAT[$tpgid]##*)})
+    wchan=""
+    read -r wchan 2>/dev/null < /proc/$tpgid/wchan
+    echo "fgwait:$child:${f[0]} $wchan $(readlink /proc/$tpgid/fd/0 2>/dev/null)"
+  fi
+
+  if [ "$PROTECT_EDITORS" = "true" ]; then
+    case "$fg_comm" in
+      vi|vim|nvim|view)
+        dirty=""
+        for p in $tpgid $kids; do
+          for fd in /proc/$p/fd/*; do
+            f=$(readlink "$fd" 2>/dev/null)
+            case "$f" in
+              *.sw?) [ "$(dd if="$f" bs=1 skip=1007 count=1 2>/dev/null)" = U ] && dirty="$dirty ${f##*/}" ;;
+            esac
+          done
+        done
+        echo "fgdirty:$child:$(echo $dirty)"
+        ;;
+    esac
   fi
+
+  [ "$RECURSE_MUX" = "true" ] || continue
+  case "$fg_comm" in
+    "tmux: server") servers="$tpgid" ;;
+    tmux*) named "tmux: server"; servers=$NAMED ;;
+    screen) servers=$(pgrep -f '^SCREEN' 2>/dev/null) ;;
+    *) continue ;;
+  esac
+  for server in $servers; do
+    for inner in ${KIDS[$server]}; do
+      inner_stat=${STAT[$inner]}
+      [ -n "$inner_stat" ] || continue
+      echo "nstat:$child:$inner_stat"
+      f=(${inner_stat##*)})
+      inner_tpgid=${f[5]}
+      [ -n "$inner_tpgid" ] && [ "$inner_tpgid" -gt 0 ] 2>/dev/null || continue
+      comm_of "$inner_tpgid"
+      echo "nfgcomm:$child:$COMM"
+      cmdline_of "$inner_tpgid"
+      echo "nfgcmd:$child:$CMDLINE"
+      echo "nfgkids:$child:${KIDS[$inner_tpgid]# }"
+    done
+  done
+done
+"#;
+
+// macOS counterpart of PROC_DUMP_SCRIPT, for systems without /proc.
+// Uses ps to synthesize records in the same format, including a minimal stat line
+// ("pid (comm) S ppid pgrp 0 tty_nr tpgid", where tty_nr is 0 only with no tty),
+// so the same classifier applies. CPU sampling is not supported here.
+//
+// Arguments: as for PROC_DUMP_SCRIPT, of which only these are used
+//   $1 = zellij PID
+//   $2 = detect_ssh_sessions ("true" or "false")
+//   $8 = battery_only ("true" or "false")
+//   $11 = the pane shells' PIDs from an earlier dump, to skip running pgrep ("" = look
+//         them up, emitting children:-:<PIDs> for the plugin to pass next time). A
+//         cached PID that's gone, or no longer zellij's child, is left out.
+//   $12 = respect_system_idle ("true" or "false"), read from the HID system's idle time
+//   $13 = max_panes_for_detection ("" = no limit)
+const MACOS_PROC_DUMP_SCRIPT: &str = r#"
+ZELLIJ_PID="$1"
+DETECT_SSH="$2"
+BATTERY_ONLY="$8"
+CHILDREN="${11}"
+SYSTEM_IDLE="${12}"
+MAX_PANES="${13}"
+
+if [ "$SYSTEM_IDLE" = "true" ]; then
+  # HIDIdleTime is in nanoseconds
+  idle=$(ioreg -c IOHIDSystem 2>/dev/null | awk '/HIDIdleTime/ { print int($NF / 1000000000); exit }')
+  echo "sysidle:-:${idle:-unknown}"
+fi
+
+if [ -z "$CHILDREN" ]; then
+  CHILDREN=$(echo $(pgrep -P "$ZELLIJ_PID" | grep -vx "$$"))
+  echo "children:-:$CHILDREN"
+fi
+
+set -- $CHILDREN
+if [ -n "$MAX_PANES" ] && [ $# -gt "$MAX_PANES" ]; then
+  echo "toomanypanes:-:$#"
+  exit 0
+fi
+
+if [ "$BATTERY_ONLY" = "true" ]; then
+  # The first line reads "Now drawing from 'AC Power'" or "... 'Battery Power'"
+  case "$(pmset -g batt 2>/dev/null | head -n 1)" in
+    *"AC Power"*) echo "power:-:ac" ;;
+    *"Battery Power"*) echo "power:-:battery" ;;
+    *) echo "power:-:unknown" ;;
+  esac
+fi
+
+if [ "$DETECT_SSH" = "true" ]; then
+  # As in PROC_DUMP_SCRIPT, sessions that a zellij client is attached through don't count
+  attached=" "
+  for client in $(pgrep -x zellij 2>/dev/null); do
+    [ "$client" = "$ZELLIJ_PID" ] && continue
+    p=$client
+    while [ "$p" -gt 1 ] 2>/dev/null; do
+      attached="$attached$p "
+      p=$(ps -o ppid= -p "$p" 2>/dev/null | tr -d ' ')
+    done
+  done
+  sessions=0
+  for sshd in $(pgrep -f '^sshd(-session)?: [^ ]+@' 2>/dev/null); do
+    case "$attached" in *" $sshd "*) ;; *) sessions=$((sessions + 1)) ;; esac
+  done
+  echo "sshsessions:-:$sessions"
+  for mosh in $(pgrep -x mosh-server 2>/dev/null); do
+    case "$attached" in *" $mosh "*) continue ;; esac
+    idle=-
+    for kid in $(pgrep -P "$mosh" 2>/dev/null); do
+      tty=$(ps -o tty= -p "$kid" 2>/dev/null | tr -d ' ')
+      case "$tty" in ttys*) atime=$(stat -f %a "/dev/$tty" 2>/dev/null) && idle=$(( $(date +%s) - atime )) ;; esac
+    done
+    echo "moshsession:-:$idle"
+  done
+fi
+
+for child in $CHILDREN; do
+  read -r pid ppid pgid tpgid tty comm <<< "$(ps -o pid=,ppid=,pgid=,tpgid=,tty=,comm= -p "$child" 2>/dev/null)"
+  [ -n "$pid" ] && [ "$ppid" = "$ZELLIJ_PID" ] || continue
+  [ "$child" = "$$" ] && continue
+  tty_nr=1
+  [ "$tty" = "??" ] && tty_nr=0
+  echo "stat:$child:$pid (${comm##*/}) S $ppid $pgid 0 $tty_nr $tpgid"
+  # `ps e` appends the environment, which is only visible for our own processes
+  echo "paneid:$child:$(ps eww -o command= -p "$child" 2>/dev/null | tr ' ' '\n' | sed -n 's/^ZELLIJ_PANE_ID=//p')"
+  [ -n "$tpgid" ] && [ "$tpgid" -gt 0 ] 2>/dev/null || continue
+  fg_comm=$(ps -o comm= -p "$tpgid" 2>/dev/null)
+  fg_comm="${fg_comm:-unknown}"
+  echo "fgcomm:$child:${fg_comm##*/}"
+  echo "fgcmd:$child:$(ps -o command= -p "$tpgid" 2>/dev/null)"
+  kids=$(echo $(pgrep -P "$tpgid" 2>/dev/null))
+  echo "fgkids:$child:$kids"
+  for p in $child $tpgid $kids; do
+    value=$(ps eww -o command= -p "$p" 2>/dev/null | tr ' ' '\n' | sed -n 's/^ZELLIJ_IDLE_KEEPAWAKE=//p')
+    case "$value" in ""|0) ;; *) echo "envkeepawake:$child:$p"; break ;; esac
+  done
 done
 "#;
 
 // Bash script to self-suspend or stop a GCE VM.
 // Fetches instance metadata from the GCE metadata server, then tries suspend first
 // and falls back to stop (for E2/GPU instances where suspend is unsupported).
-// $1 = action: "suspend" or "stop".
+// $1 = action: "suspend", "stop", or "suspend-only" to fail instead of falling back.
+// $2 = gcloud_path (empty = gcloud on PATH)
+// Metadata fetches give up after 5s, so a hung metadata server fails the attempt instead
+// of holding it forever. The gcloud call itself isn't timed: a suspend that works
+// freezes it until the VM resumes.
 const SUSPEND_SCRIPT: &str = r#"
-VM_NAME=$(curl -sf "http://metadata.google.internal/computeMetadata/v1/instance/name" -H "Metadata-Flavor: Google") || { echo "ERROR: failed to fetch VM name from metadata server"; exit 1; }
-VM_ZONE=$(curl -sf "http://metadata.google.internal/computeMetadata/v1/instance/zone" -H "Metadata-Flavor: Google" | cut -d '/' -f 4) || { echo "ERROR: failed to fetch VM zone from metadata server"; exit 1; }
-VM_PROJECT=$(curl -sf "http://metadata.google.internal/computeMetadata/v1/project/project-id" -H "Metadata-Flavor: Google") || { echo "ERROR: failed to fetch project ID from metadata server"; exit 1; }
+GCLOUD="${2:-gcloud}"
+metadata() {
+  curl -sf --max-time 5 -H "Metadata-Flavor: Google" "http://metadata.google.internal/computeMetadata/v1/$1"
+}
+# $1 = what was being fetched, $2 = curl's exit status (28 when --max-time ran out)
+metadata_failed() {
+  if [ "$2" = 28 ]; then
+    echo "ERROR: metadata server didn't answer within 5s fetching the $1"
+  else
+    echo "ERROR: failed to fetch the $1 from metadata server (curl exit $2)"
+  fi
+  exit 1
+}
+VM_NAME=$(metadata instance/name) || metadata_failed "VM name" $?
+VM_ZONE=$(metadata instance/zone) || metadata_failed "VM zone" $?
+VM_ZONE=${VM_ZONE##*/}
+VM_PROJECT=$(metadata project/project-id) || metadata_failed "project ID" $?
 
 ACTION="${1:-suspend}"
 
 if [ "$ACTION" = "stop" ]; then
   echo "Stopping $VM_NAME in $VM_ZONE ($VM_PROJECT)..."
-  gcloud compute instances stop "$VM_NAME" --zone="$VM_ZONE" --project="$VM_PROJECT" --quiet
-elif [ "$ACTION" = "suspend" ]; then
+  "$GCLOUD" compute instances stop "$VM_NAME" --zone="$VM_ZONE" --project="$VM_PROJECT" --quiet
+elif [ "$ACTION" = "suspend" ] || [ "$ACTION" = "suspend-only" ]; then
   echo "Suspending $VM_NAME in $VM_ZONE ($VM_PROJECT)..."
-  if ! gcloud compute instances suspend "$VM_NAME" --zone="$VM_ZONE" --project="$VM_PROJECT" --quiet 2>/tmp/zellij-idle-suspend-err; then
+  if ! "$GCLOUD" compute instances suspend "$VM_NAME" --zone="$VM_ZONE" --project="$VM_PROJECT" --quiet 2>/tmp/zellij-idle-suspend-err; then
+    [ "$ACTION" = "suspend-only" ] && { cat /tmp/zellij-idle-suspend-err >&2; exit 1; }
     echo "Suspend failed, falling back to stop..."
-    gcloud compute instances stop "$VM_NAME" --zone="$VM_ZONE" --project="$VM_PROJECT" --quiet
+    "$GCLOUD" compute instances stop "$VM_NAME" --zone="$VM_ZONE" --project="$VM_PROJECT" --quiet
+  fi
+fi
+"#;
+
+// Bash script to self-stop or hibernate an AWS EC2 instance.
+// Fetches the instance ID and region from IMDS (using an IMDSv2 session token when
+// available, falling back to IMDSv1), then runs a single `aws ec2 stop-instances`.
+// There is no fallback between actions: hibernate requires it to be enabled at launch.
+// $1 = action: "stop" or "hibernate".
+// $2 = aws_path (empty = aws on PATH)
+// As in SUSPEND_SCRIPT, IMDS fetches give up after 5s.
+const AWS_SUSPEND_SCRIPT: &str = r#"
+AWS="${2:-aws}"
+IMDS="http://169.254.169.254/latest"
+# As in SUSPEND_SCRIPT: $1 = what was being fetched, $2 = curl's exit status
+metadata_failed() {
+  if [ "$2" = 28 ]; then
+    echo "ERROR: IMDS didn't answer within 5s fetching the $1"
+  else
+    echo "ERROR: failed to fetch the $1 from IMDS (curl exit $2)"
+  fi
+  exit 1
+}
+TOKEN=$(curl -sf --max-time 5 -X PUT "$IMDS/api/token" -H "X-aws-ec2-metadata-token-ttl-seconds: 60") || TOKEN=""
+if [ -n "$TOKEN" ]; then
+  HDR=(-H "X-aws-ec2-metadata-token: $TOKEN")
+else
+  HDR=()
+fi
+
+INSTANCE_ID=$(curl -sf --max-time 5 "${HDR[@]}" "$IMDS/meta-data/instance-id") || metadata_failed "instance ID" $?
+REGION=$(curl -sf --max-time 5 "${HDR[@]}" "$IMDS/meta-data/placement/region") || metadata_failed "region" $?
+
+ACTION="${1:-stop}"
+
+if [ "$ACTION" = "hibernate" ]; then
+  echo "Hibernating $INSTANCE_ID in $REGION..."
+  "$AWS" ec2 stop-instances --instance-ids "$INSTANCE_ID" --region "$REGION" --hibernate
+else
+  echo "Stopping $INSTANCE_ID in $REGION..."
+  "$AWS" ec2 stop-instances --instance-ids "$INSTANCE_ID" --region "$REGION"
+fi
+"#;
+
+// Bash script to deallocate or stop an Azure VM.
+// Fetches the VM name, resource group, and subscription from the Azure IMDS (each
+// field in text form, so no JSON parser is needed), then runs `az vm`. Only
+// deallocate stops compute billing; a stopped VM is still billed.
+// $1 = action: "deallocate" or "stop".
+// $2 = az_path (empty = az on PATH)
+// As in SUSPEND_SCRIPT, IMDS fetches give up after 5s.
+const AZURE_SUSPEND_SCRIPT: &str = r#"
+AZ="${2:-az}"
+IMDS="http://169.254.169.254/metadata/instance/compute"
+imds() {
+  curl -sf --max-time 5 -H "Metadata:true" "$IMDS/$1?api-version=2021-02-01&format=text"
+}
+# As in SUSPEND_SCRIPT: $1 = what was being fetched, $2 = curl's exit status
+metadata_failed() {
+  if [ "$2" = 28 ]; then
+    echo "ERROR: IMDS didn't answer within 5s fetching the $1"
+  else
+    echo "ERROR: failed to fetch the $1 from IMDS (curl exit $2)"
+  fi
+  exit 1
+}
+
+VM_NAME=$(imds name) || metadata_failed "VM name" $?
+RESOURCE_GROUP=$(imds resourceGroupName) || metadata_failed "resource group" $?
+SUBSCRIPTION=$(imds subscriptionId) || metadata_failed "subscription" $?
+
+ACTION="${1:-deallocate}"
+
+if [ "$ACTION" = "stop" ]; then
+  echo "Stopping $VM_NAME in $RESOURCE_GROUP ($SUBSCRIPTION)..."
+  "$AZ" vm stop --name "$VM_NAME" --resource-group "$RESOURCE_GROUP" --subscription "$SUBSCRIPTION"
+else
+  echo "Deallocating $VM_NAME in $RESOURCE_GROUP ($SUBSCRIPTION)..."
+  "$AZ" vm deallocate --name "$VM_NAME" --resource-group "$RESOURCE_GROUP" --subscription "$SUBSCRIPTION"
+fi
+"#;
+
+// Bash script to power off or suspend the local machine, for non-cloud hosts.
+// Needs the privileges to do so, e.g. a polkit rule or running zellij as root.
+// $1 = action: "poweroff", "suspend", or what a suspend_action "systemd:<target>" names:
+// a systemctl verb, or a .target unit to start.
+const LOCAL_SUSPEND_SCRIPT: &str = r#"
+ACTION="${1:-poweroff}"
+
+case "$ACTION" in
+  poweroff)
+    echo "Powering off $(hostname)..."
+    systemctl poweroff || shutdown -h now
+    ;;
+  suspend)
+    echo "Suspending $(hostname)..."
+    systemctl suspend
+    ;;
+  *.target)
+    echo "Starting $ACTION on $(hostname)..."
+    systemctl start "$ACTION"
+    ;;
+  *)
+    echo "Running systemctl $ACTION on $(hostname)..."
+    systemctl "$ACTION"
+    ;;
+esac
+"#;
+
+// Bash script for suspend_action "auto": prints which cloud's metadata server answers,
+// trying GCE, then AWS (IMDSv2), then Azure, or "none" if none do.
+const DETECT_PROVIDER_SCRIPT: &str = r#"
+if curl -sf --max-time 2 -H "Metadata-Flavor: Google" \
+    "http://metadata.google.internal/computeMetadata/v1/instance/name" >/dev/null; then
+  echo gce
+elif curl -sf --max-time 2 -X PUT -H "X-aws-ec2-metadata-token-ttl-seconds: 60" \
+    "http://169.254.169.254/latest/api/token" >/dev/null; then
+  echo aws
+elif curl -sf --max-time 2 -H "Metadata:true" \
+    "http://169.254.169.254/metadata/instance/compute/name?api-version=2021-02-01&format=text" >/dev/null; then
+  echo azure
+else
+  echo none
+fi
+"#;
+
+// Bash script for zellij-idle-selfcheck: checks what suspending and idle detection rely on
+// and prints one `ok: ...`, `FAIL: ...` or `skip: ...` line per check, then `---` and the
+// output of a dry idle scan for the plugin to classify.
+// $1 = the suspend action's CLI, possibly a full path ("" for a custom suspend_command or "none")
+// $2 = the cloud whose metadata server the action needs: gce, aws, azure or ""
+// $3... = the idle check command line
+const SELFCHECK_SCRIPT: &str = r#"
+CLI="$1"
+CLOUD="$2"
+shift 2
+
+if [ -z "$CLI" ]; then
+  echo "skip: no suspend CLI to check"
+elif ! command -v "$CLI" >/dev/null 2>&1; then
+  echo "FAIL: $CLI not found on PATH"
+else
+  echo "ok: $CLI is $(command -v "$CLI")"
+  case "${CLI##*/}" in
+    gcloud) account=$(timeout 20 "$CLI" auth list --filter=status:ACTIVE --format='value(account)' 2>/dev/null | head -n 1) ;;
+    aws) account=$(timeout 20 "$CLI" sts get-caller-identity --query Arn --output text 2>/dev/null) ;;
+    az) account=$(timeout 20 "$CLI" account show --query user.name --output tsv 2>/dev/null) ;;
+    *) account=- ;;
+  esac
+  if [ -z "$account" ]; then
+    echo "FAIL: $CLI has no active credentials"
+  elif [ "$account" != - ]; then
+    echo "ok: $CLI is authenticated as $account"
   fi
 fi
+
+case "$CLOUD" in
+  gce) curl -sf --max-time 2 -H "Metadata-Flavor: Google" \
+         "http://metadata.google.internal/computeMetadata/v1/instance/name" >/dev/null ;;
+  aws) curl -sf --max-time 2 -X PUT -H "X-aws-ec2-metadata-token-ttl-seconds: 60" \
+         "http://169.254.169.254/latest/api/token" >/dev/null ;;
+  azure) curl -sf --max-time 2 -H "Metadata:true" \
+           "http://169.254.169.254/metadata/instance/compute/name?api-version=2021-02-01&format=text" >/dev/null ;;
+  *) false ;;
+esac
+status=$?
+if [ -z "$CLOUD" ]; then
+  echo "skip: the suspend action doesn't use a metadata server"
+elif [ "$status" = 0 ]; then
+  echo "ok: $CLOUD metadata server reachable"
+else
+  echo "FAIL: $CLOUD metadata server unreachable"
+fi
+
+dump=$("$@" 2>&1)
+echo "---"
+echo "$dump"
+"#;
+
+// Bash script that prints the config file zellij-idle-reload was given.
+// $1 = its path, absolute or starting with ~/ (expanded here)
+const READ_CONFIG_SCRIPT: &str = r#"
+cat -- "${1/#\~/$HOME}"
+"#;
+
+// Bash script to rewrite heartbeat_file, through a temporary file so a reader never sees
+// it half-written.
+// $1 = heartbeat_file (a leading ~ is expanded)
+// $2 = the contents
+const HEARTBEAT_SCRIPT: &str = r#"
+file="${1/#\~/$HOME}"
+mkdir -p "$(dirname "$file")"
+printf '%s' "$2" > "$file.tmp" && mv -f "$file.tmp" "$file"
+"#;
+
+// Bash script that reads or writes the savings counter file, which lives next to the log
+// so it survives a stopped box's reboot (the plugin's own /tmp doesn't).
+// $1 = "read" or "write"
+// $2 = the contents to write
+// Reading also prints `boot_unix=<boot time>` where /proc/uptime exists, to tell how long
+// a stopped box was down.
+const SAVINGS_FILE_SCRIPT: &str = r#"
+file="$HOME/.local/share/zellij-idle/savings"
+if [ "$1" = read ]; then
+  cat "$file" 2>/dev/null
+  read -r up _ 2>/dev/null < /proc/uptime && echo "boot_unix=$(( $(date +%s) - ${up%%.*} ))"
+else
+  mkdir -p "$(dirname "$file")"
+  printf '%s' "$2" > "$file.tmp" && mv -f "$file.tmp" "$file"
+fi
+"#;
+
+// Bash script to POST a record of the suspend to post_suspend_webhook.
+// $1 = webhook URL
+// $2 = suspend_action
+// $3 = idle duration in seconds
+// The VM name is the hostname, which is what GCE, EC2 (with resource names) and Azure
+// set it to by default.
+const POST_SUSPEND_WEBHOOK_SCRIPT: &str = r#"
+body=$(printf '{"vm":"%s","action":"%s","idle_secs":%s,"ts":%s}' "$(hostname)" "$2" "$3" "$(date +%s)")
+curl -sS -f --max-time 10 -XPOST -H 'Content-Type: application/json' --data "$body" "$1"
 "#;
 
 struct State {
+    // Set once permissions are granted and polling has started
     loaded: bool,
+    // The user denied the permission request, so the plugin can't do anything
+    permission_denied: bool,
     zellij_pid: u32,
 
     // Idle detection
     is_idle: bool,
     idle_elapsed_secs: f64,
     active_pane_count: usize,
+    // Pane shells found by the last idle check (zellij's direct child processes)
+    shell_count: usize,
+    // Terminal panes with a running process, per zellij's last PaneUpdate
+    zellij_pane_count: Option<usize>,
+    // The pane shells' PIDs, passed to the macOS idle check so it can skip pgrep (the Linux
+    // one reads them from /proc with everything else, so keeps no cache); looked up again
+    // every child_pid_refresh_polls polls (0 = every poll), when a shell exits, and when
+    // zellij's pane count changes
+    child_pids: Option<Vec<u32>>,
+    child_pid_refresh_polls: u64,
+    // Idle checks since child_pids was looked up, and the totals, for the savings log
+    child_pids_age: u64,
+    child_pid_lookups: u64,
+    child_pid_reuses: u64,
+    // Replaces the built-in idle check: a bash command printing `STATE:PID:DETAIL` lines
+    custom_idle_check: String,
+    // The lines the last custom idle check got wrong, logged when they change
+    custom_idle_check_malformed: Vec<String>,
+    // Above this many pane shells the idle check doesn't scan them and the box counts as
+    // active (0 = no limit); too_many_panes is whether the last check was over
+    max_panes_for_detection: usize,
+    too_many_panes: bool,
+    // Clients attached to this session, per zellij's last SessionUpdate
+    connected_clients: Option<usize>,
     active_processes: Vec<String>,
+    // The most common reason among active panes and box signals, e.g. `claude-working`
+    active_reason: Option<String>,
+    // Consecutive idle checks that found no panes
+    empty_poll_streak: u32,
+    // Consecutive idle checks that found the box idle, and how many it takes to go idle
+    idle_poll_streak: u32,
+    consecutive_idle_polls: u32,
 
-    // Polling counters — elapsed idle time = (poll_count - last_activity_poll_count) * POLL_INTERVAL_SECS
+    // Polling counters. The poll interval varies (see next_poll_interval), so clock_secs
+    // sums the intervals of the ticks so far: idle time = clock_secs - last_activity_secs
     poll_count: u64,
-    last_activity_poll_count: u64,
+    clock_secs: f64,
+    last_activity_secs: f64,
+    last_tick_unix: f64,
+    // The interval the pending timer was set for, and when it should fire; a timer
+    // firing well before next_tick_unix was superseded by a snap back to the fast interval
+    tick_interval_secs: f64,
+    next_tick_unix: f64,
+    // What the pending timer was actually set for, tick_interval_secs plus poll jitter
+    tick_delay_secs: f64,
+    // Idle transitions and countdown entry are held off while clock_secs < snooze_until_secs
+    snooze_until_secs: f64,
+    // Set while an idle check's RunCommandResult is outstanding, so slow checks don't pile up;
+    // a result that hasn't come back after IDLE_CHECK_STALE_POLLS polls is given up on
+    idle_check_in_flight: bool,
+    idle_check_started_poll: u64,
+    // event_driven: set by pane/tab/session updates, consumed by the next tick
+    idle_check_requested: bool,
+    last_idle_check_secs: f64,
 
     // Countdown state
     countdown_active: bool,
     countdown_remaining: f64,
+    // The running countdown was started by max_session_secs, so activity can't cancel it
+    maxtime_countdown: bool,
+    // When this session started (load, or the last resume), and whether it's been extended
+    session_start_unix: f64,
+    session_extended: bool,
+    // Number of countdown_warnings thresholds crossed in the current countdown
+    countdown_stage: usize,
+    // Remaining countdown seconds at which to ring the terminal bell and invert the banner (0 = off)
+    bell_at_secs: f64,
+    // The bell has rung in this countdown, or is waiting for the next render to ring it
+    bell_rung: bool,
+    // Seconds the SUSPEND! banner shows after the countdown ends before the suspend starts
+    // (0 = none), and when on clock_secs that hold is up
+    suspend_banner_hold_secs: f64,
+    suspend_hold_until_secs: Option<f64>,
+    // A countdown that runs out waits at CONFIRM for zellij-idle-confirm rather than
+    // suspending; input while waiting, once CONFIRM_GRACE_SECS have passed, cancels it
+    require_confirm_suspend: bool,
+    awaiting_confirm: bool,
+    confirm_input_unix: Option<f64>,
+    bell_pending: bool,
     suspend_triggered: bool,
+    // warn_first_cycle: the session's first countdown only shows WOULD SUSPEND, then resets
+    warn_first_cycle: bool,
+    first_cycle_warned: bool,
 
     // Suspend command state
     suspend_command_sent: bool,
-    gcloud_missing: bool,
+    cli_missing: bool,
+    // Suspend commands issued since the last trigger, and when to retry a failed one
+    suspend_attempts: u32,
+    suspend_retry_at_unix: Option<f64>,
 
-    // Config (from layout.kdl)
+    // Set from `uname -s` at load; selects MACOS_PROC_DUMP_SCRIPT over the /proc one
+    platform_macos: bool,
+
+    // Config (from layout.kdl, plus any zellij-idle-config overrides)
+    config: BTreeMap<String, String>,
+    // When false, keep polling but never count down or suspend
+    enabled: bool,
+    poll_interval_secs: f64,
+    // While idle for poll_backoff_after_secs, the interval doubles up to this cap
+    max_poll_interval_secs: f64,
+    poll_backoff_after_secs: f64,
+    // Each timer is set up to this much early or late (0 = exactly on the interval), so a
+    // fleet of boxes doesn't poll in step; the generator is seeded from zellij_pid
+    poll_jitter_secs: f64,
+    jitter_rng: u64,
     idle_timeout_secs: f64,
+    // Rules that override idle_timeout_secs by weekday/time; the first match wins
+    timeout_schedule: Vec<TimeoutRule>,
     countdown_secs: f64,
+    // Remaining-seconds thresholds, sorted descending, that escalate the countdown
+    countdown_warnings: Vec<f64>,
+    // countdown_format "mmss": render durations as M:SS / XmYYs instead of plain seconds
+    countdown_mmss: bool,
+    status_formats: StatusFormats,
+    // Config values that failed to parse, each `key: problem`; the defaults apply instead.
+    // The bar shows CONFIG ERR for the first CONFIG_ERR_RENDERS renders after loading.
+    config_errors: Vec<String>,
+    config_err_renders: u32,
+    // SGR parameters (e.g. "41;97;1") for each state; no_color drops escape codes entirely
+    color_idle: String,
+    color_countdown: String,
+    // The countdown's intermediate warning stages, between color_countdown and color_suspend
+    color_warning: String,
+    color_suspend: String,
+    color_snooze: String,
+    color_active: String,
+    no_color: bool,
+    // The action being tried: the first of suspend_chain, or a later one falling back
     suspend_action: String,
+    // suspend_action as a comma-separated list (e.g. "gce-suspend,gce-stop,systemd-suspend"):
+    // each failed action falls back to the next, and suspend_chain_index is the current one
+    suspend_chain: Vec<String>,
+    suspend_chain_index: usize,
+    // An action in suspend_action that isn't one of SUSPEND_ACTIONS; nothing is suspended
+    // rather than guessing what was meant
+    bad_suspend_action: Option<String>,
+    // Why the plugin has given up on suspending (a bad suspend_action, or retries used
+    // up), shown as SUSPEND FAILED until input, a config change or a suspend clears it
+    suspend_disabled_reason: Option<String>,
+    // suspend_action "auto": the provider whose metadata server answered, once probed
+    detected_provider: Option<String>,
+    suspend_command: String,
+    // What suspend_action "lock" runs; empty detaches every client
+    lock_command: String,
+    // What to do instead when the box has no way to suspend: "log", "detach" or
+    // "command:<shell command>"
+    fallback_action: String,
+    // Who runs the suspend command, through `sudo -n` (empty = the plugin's user), and
+    // KEY=VALUE pairs to add to its environment
+    suspend_run_as: String,
+    suspend_env: Vec<String>,
+    // Where the provider CLIs live, for images that install them off the plugin's PATH
+    gcloud_path: String,
+    aws_path: String,
+    az_path: String,
+    dry_run: bool,
+    snooze_secs: f64,
+    // Default length of a zellij-idle-extend, and the last one while the bar still shows it
+    extend_secs: f64,
+    extend_flash: Option<(f64, f64)>,
+    notify_command: String,
+    // Run before suspending; a non-zero exit cancels the suspend
+    pre_suspend_command: String,
+    // Run at load when the savings file says this plugin suspended the box, i.e. on the
+    // first load after it came back
+    post_resume_command: String,
+    // Suspend counters for the savings estimate, and what an hour of the box costs (0 = unknown)
+    savings: Savings,
+    // The counter file has been read, so writing it won't clobber older counts
+    savings_loaded: bool,
+    hourly_cost: f64,
+    // Run the selfcheck at load, and the CLI pipes waiting for a selfcheck report
+    selfcheck_on_load: bool,
+    selfcheck_replies: Vec<String>,
+    // CLI pipes waiting for zellij-idle-reload to read its config file
+    reload_replies: Vec<String>,
+    // Save the session layout to layout_dump_path (empty = default) before suspending
+    dump_layout_before_suspend: bool,
+    layout_dump_path: String,
+    // This session's name, per zellij's last SessionUpdate
+    session_name: Option<String>,
+    // URL that gets a JSON record of each suspend, once the command is dispatched or,
+    // with post_suspend_webhook_on "success", once it exits 0
+    post_suspend_webhook: String,
+    webhook_on_success: bool,
+    // Hard cap on session length (0 = off); the first keypress during its countdown
+    // extends it once by max_session_extension_secs (0 = no extension)
+    max_session_secs: f64,
+    max_session_extension_secs: f64,
+    // Failed suspend commands are retried up to suspend_max_retries times, retry_backoff_secs apart
+    suspend_max_retries: u32,
+    retry_backoff_secs: f64,
+    // A suspend command that hasn't reported back after this long counts as failed (0 = never);
+    // suspend_started_secs is when the one under way was sent, and suspend_run numbers them
+    // so a timed-out one that finishes late is told apart
+    suspend_timeout_secs: f64,
+    suspend_started_secs: Option<f64>,
+    suspend_run: u64,
     claude_code_idle_detection: bool,
+    // Judge an agent by its process tree's CPU use (percent of one core) and running
+    // processes instead of by whether it has children (0 = off)
+    claude_working_cpu_threshold: f64,
+    // Each pane's foreground tree CPU ticks and when they were read, and the CPU use
+    // worked out from them, by shell pid
+    agent_ticks: BTreeMap<String, (f64, u64)>,
+    agent_cpu_pct: BTreeMap<String, f64>,
+    agent_process_patterns: Vec<String>,
     ignore_processes: Vec<String>,
+    ignore_patterns: Vec<Regex>,
+    include_processes: Vec<String>,
+    detect_ssh_sessions: bool,
+    // A mosh session whose terminal has had no input for this long counts as left (0 = never)
+    mosh_idle_after_secs: f64,
+    recurse_multiplexers: bool,
+    // Descendant CPU usage (percent of one core) above which the box counts as active; 0 = off
+    cpu_active_threshold_pct: f64,
+    detect_gpu: bool,
+    // Utilization (percent) above which any GPU counts as active
+    gpu_active_threshold_pct: f64,
+    // Network throughput (kilobits/s, all interfaces but lo) above which the box counts as active
+    detect_network_active: bool,
+    network_active_kbps: f64,
+    // Running docker/podman containers above container_cpu_threshold_pct (percent of one core)
+    // count as active; the engines' paths are looked up by the first idle check
+    detect_containers: bool,
+    container_cpu_threshold_pct: f64,
+    container_engines: Option<String>,
+    // The containererror line of the last dump, so a failing engine is logged once
+    container_error: Option<String>,
+    // Local-time window (minutes since midnight, start..end) during which suspend is suppressed
+    active_window: Option<(u32, u32)>,
+    // Seconds since boot before suspend is allowed, so a freshly started box isn't put straight back to sleep
+    min_uptime_secs: f64,
+    // Keep the box awake while an ignored editor has unsaved changes
+    protect_editors: bool,
+    // Fraction of pane shells that must be idle for the box to count as idle (1 = all)
+    idle_ratio_threshold: f64,
+    // A foreground process blocked reading its terminal counts as idle (`stdin-wait`)
+    treat_stdin_wait_as_idle: bool,
+    // Tab names whose panes never count, and (when non-empty) the only tabs whose panes do
+    ignore_tabs: Vec<String>,
+    count_tabs: Vec<String>,
+    // Tab names by position, per zellij's last TabUpdate
+    tab_names: BTreeMap<usize, String>,
+    // Terminal pane id -> owning tab position, per zellij's last PaneUpdate
+    pane_tabs: BTreeMap<u32, usize>,
+    // The shell -> pane -> tab mapping last logged, so it's only logged when it changes
+    last_pane_mapping: String,
+    // Only count down and suspend while no client is attached to the session
+    require_no_clients: bool,
+    // Idle timeout while no client is attached, which likely means the session was left (0 = off)
+    detached_idle_timeout_secs: f64,
+    // Only suspend on battery power, after battery_timeout_secs of idle there when set (0 = idle timeout)
+    battery_only: bool,
+    battery_timeout_secs: f64,
+    // "ac", "battery" or "unknown", per the last idle check (battery_only)
+    power_source: Option<String>,
+    // When false, a keypress only cancels a running countdown and keeps the idle credit
+    keypress_resets_idle: bool,
+    // When input was last handled, to skip the rest of a burst
+    last_input_unix: f64,
+    // Input within this long of the input before it is noise and resets nothing (0 = off)
+    input_debounce_secs: f64,
+    // When input last got past input_debounce_secs
+    last_accepted_input_unix: f64,
+    // Input events dropped by input_debounce_secs
+    input_noise_events: u64,
+    // Show why the box is active (e.g. `ACTIVE: claude-working`) instead of the process list
+    show_active_reason: bool,
+    // Show the time left until the countdown while idle (`IDLE→4m12s`) instead of the time idle
+    show_arming_progress: bool,
+    // Append whether each of the last SPARKLINE_POLLS polls kept the box awake (`▁▁█▁`)
+    show_sparkline: bool,
+    activity_history: VecDeque<bool>,
+    // Only scan /proc after zellij reports a pane/tab/session change, or every safety_poll_secs
+    event_driven: bool,
+    safety_poll_secs: f64,
+
+    // Local wall-clock time in minutes since midnight, refreshed via `date` each poll
+    local_time_minutes: Option<u32>,
+    // Local weekday, Monday = 0, refreshed along with local_time_minutes
+    local_weekday: Option<usize>,
+    // Label of the timeout_schedule rule in effect, to log when it changes
+    timeout_profile: Option<String>,
+    suppressed_reason: Option<&'static str>,
+    // Boot time as unix seconds, derived from /proc/uptime at load; None if unavailable
+    boot_unix: Option<f64>,
+
+    // Previous (unix time, cpu ticks) sample of zellij's descendants, for cpu_active_threshold_pct
+    last_cpu_sample: Option<(f64, u64)>,
+    // Set once nvidia-smi has been reported missing, so it's only logged once
+    gpu_missing_logged: bool,
+    // Input to the machine itself (a desktop session or the console) counts as activity
+    respect_system_idle: bool,
+    system_idle_missing_logged: bool,
+    // Previous (unix time, rx+tx bytes) sample, for network_active_kbps
+    last_net_sample: Option<(f64, u64)>,
+
+    // State file — see persist.rs; write failures are only logged once
+    state_file_error_logged: bool,
 
-    // Log buffer — flushed to ~/.local/share/zellij-idle/zellij-idle.log each poll
+    // Log buffer — flushed to log_file (default ~/.local/share/zellij-idle/zellij-idle.log) each poll
     log_buffer: Vec<String>,
+    log_file: String,
+    // Rewritten after each successful idle check for liveness monitoring (empty = off)
+    heartbeat_file: String,
+    heartbeat_failed: bool,
+    log_max_bytes: u64,
+    // log_format "json": one JSON object per line instead of text
+    log_json: bool,
 }
 
 impl Default for State {
     fn default() -> Self {
         Self {
             loaded: false,
+            permission_denied: false,
             zellij_pid: 0,
             is_idle: false,
             idle_elapsed_secs: 0.0,
             active_pane_count: 0,
+            shell_count: 0,
+            zellij_pane_count: None,
+            child_pids: None,
+            child_pid_refresh_polls: DEFAULT_CHILD_PID_REFRESH_POLLS,
+            child_pids_age: 0,
+            child_pid_lookups: 0,
+            custom_idle_check: String::new(),
+            custom_idle_check_malformed: Vec::new(),
+            max_panes_for_detection: 0,
+            too_many_panes: false,
+            child_pid_reuses: 0,
             active_processes: Vec::new(),
+            active_reason: None,
+            empty_poll_streak: 0,
+            idle_poll_streak: 0,
+            consecutive_idle_polls: 1,
             poll_count: 0,
-            last_activity_poll_count: 0,
+            clock_secs: 0.0,
+            last_activity_secs: 0.0,
+            last_tick_unix: 0.0,
+            snooze_until_secs: 0.0,
+            idle_check_in_flight: false,
+            idle_check_started_poll: 0,
+            idle_check_requested: false,
+            last_idle_check_secs: 0.0,
             countdown_active: false,
             countdown_remaining: 0.0,
+            countdown_stage: 0,
+            maxtime_countdown: false,
+            session_start_unix: 0.0,
+            session_extended: false,
             suspend_triggered: false,
+            warn_first_cycle: false,
+            first_cycle_warned: false,
             suspend_command_sent: false,
-            gcloud_missing: false,
+            cli_missing: false,
+            suspend_attempts: 0,
+            suspend_retry_at_unix: None,
+            platform_macos: false,
+            config: BTreeMap::new(),
+            enabled: true,
+            poll_interval_secs: 0.0,
+            max_poll_interval_secs: DEFAULT_MAX_POLL_INTERVAL_SECS,
+            poll_backoff_after_secs: DEFAULT_POLL_BACKOFF_AFTER_SECS,
+            poll_jitter_secs: 0.0,
+            jitter_rng: 0,
+            tick_interval_secs: 0.0,
+            tick_delay_secs: 0.0,
+            next_tick_unix: 0.0,
             idle_timeout_secs: 0.0,
+            timeout_schedule: Vec::new(),
             countdown_secs: 0.0,
+            countdown_warnings: Vec::new(),
+            bell_at_secs: 0.0,
+            suspend_banner_hold_secs: 0.0,
+            suspend_hold_until_secs: None,
+            require_confirm_suspend: false,
+            awaiting_confirm: false,
+            confirm_input_unix: None,
+            bell_rung: false,
+            bell_pending: false,
+            countdown_mmss: false,
+            status_formats: StatusFormats::default(),
+            config_errors: Vec::new(),
+            config_err_renders: 0,
+            color_idle: DEFAULT_COLOR_IDLE.to_string(),
+            color_countdown: DEFAULT_COLOR_COUNTDOWN.to_string(),
+            color_warning: DEFAULT_COLOR_WARNING.to_string(),
+            color_suspend: DEFAULT_COLOR_SUSPEND.to_string(),
+            color_snooze: DEFAULT_COLOR_SNOOZE.to_string(),
+            color_active: DEFAULT_COLOR_ACTIVE.to_string(),
+            no_color: false,
             suspend_action: String::new(),
+            suspend_chain: Vec::new(),
+            suspend_chain_index: 0,
+            bad_suspend_action: None,
+            suspend_disabled_reason: None,
+            detected_provider: None,
+            suspend_command: String::new(),
+            lock_command: String::new(),
+            fallback_action: DEFAULT_FALLBACK_ACTION.to_string(),
+            suspend_run_as: String::new(),
+            suspend_env: Vec::new(),
+            gcloud_path: "gcloud".to_string(),
+            aws_path: "aws".to_string(),
+            az_path: "az".to_string(),
+            dry_run: false,
+            snooze_secs: 0.0,
+            extend_secs: DEFAULT_EXTEND_SECS,
+            extend_flash: None,
+            notify_command: String::new(),
+            pre_suspend_command: String::new(),
+            post_resume_command: String::new(),
+            savings: Savings::default(),
+            savings_loaded: false,
+            hourly_cost: 0.0,
+            selfcheck_on_load: false,
+            selfcheck_replies: Vec::new(),
+            reload_replies: Vec::new(),
+            dump_layout_before_suspend: false,
+            layout_dump_path: String::new(),
+            session_name: None,
+            post_suspend_webhook: String::new(),
+            webhook_on_success: false,
+            max_session_secs: 0.0,
+            max_session_extension_secs: 0.0,
+            suspend_max_retries: DEFAULT_SUSPEND_MAX_RETRIES,
+            retry_backoff_secs: DEFAULT_RETRY_BACKOFF_SECS,
+            suspend_timeout_secs: DEFAULT_SUSPEND_TIMEOUT_SECS,
+            suspend_started_secs: None,
+            suspend_run: 0,
             claude_code_idle_detection: true,
+            claude_working_cpu_threshold: 0.0,
+            agent_ticks: BTreeMap::new(),
+            agent_cpu_pct: BTreeMap::new(),
+            agent_process_patterns: Vec::new(),
             ignore_processes: Vec::new(),
+            ignore_patterns: Vec::new(),
+            include_processes: Vec::new(),
+            detect_ssh_sessions: true,
+            mosh_idle_after_secs: 0.0,
+            recurse_multiplexers: true,
+            cpu_active_threshold_pct: 0.0,
+            detect_gpu: false,
+            gpu_active_threshold_pct: DEFAULT_GPU_ACTIVE_THRESHOLD_PCT,
+            detect_network_active: false,
+            network_active_kbps: DEFAULT_NETWORK_ACTIVE_KBPS,
+            detect_containers: false,
+            container_cpu_threshold_pct: DEFAULT_CONTAINER_CPU_THRESHOLD_PCT,
+            container_engines: None,
+            container_error: None,
+            active_window: None,
+            min_uptime_secs: 0.0,
+            keypress_resets_idle: true,
+            last_input_unix: 0.0,
+            input_debounce_secs: 0.0,
+            last_accepted_input_unix: 0.0,
+            input_noise_events: 0,
+            require_no_clients: false,
+            detached_idle_timeout_secs: 0.0,
+            battery_only: false,
+            battery_timeout_secs: 0.0,
+            power_source: None,
+            protect_editors: false,
+            idle_ratio_threshold: 1.0,
+            treat_stdin_wait_as_idle: false,
+            ignore_tabs: Vec::new(),
+            count_tabs: Vec::new(),
+            tab_names: BTreeMap::new(),
+            pane_tabs: BTreeMap::new(),
+            last_pane_mapping: String::new(),
+            connected_clients: None,
+            show_active_reason: false,
+            show_arming_progress: false,
+            show_sparkline: false,
+            activity_history: VecDeque::new(),
+            event_driven: false,
+            safety_poll_secs: DEFAULT_SAFETY_POLL_SECS,
+            local_time_minutes: None,
+            local_weekday: None,
+            timeout_profile: None,
+            suppressed_reason: None,
+            boot_unix: None,
+            last_cpu_sample: None,
+            gpu_missing_logged: false,
+            respect_system_idle: false,
+            system_idle_missing_logged: false,
+            last_net_sample: None,
+            state_file_error_logged: false,
             log_buffer: Vec::new(),
+            log_file: String::new(),
+            heartbeat_file: String::new(),
+            heartbeat_failed: false,
+            log_json: false,
+            log_max_bytes: DEFAULT_LOG_MAX_BYTES,
+        }
+    }
+}
+
+/// Status-bar templates, one per displayed state. Placeholders: `{state}`, `{elapsed}`,
+/// `{remaining}`, `{reason}`, `{procs}`, `{count}`, `{panes}` and `{profile}`; unknown
+/// ones are left as-is.
+struct StatusFormats {
+    idle: String,
+    // Idle past the timeout, but suspend is held off (see suspend_suppressed_reason)
+    suppressed: String,
+    countdown: String,
+    // Countdown started by max_session_secs
+    maxtime: String,
+    snooze: String,
+    suspend: String,
+    active: String,
+    // The countdown of warn_first_cycle's first idle cycle, which doesn't suspend
+    would_suspend: String,
+    // countdown and suspend while the suspend_action step to come is "lock", which
+    // doesn't suspend, so they don't say it will
+    lock_countdown: String,
+    lock_suspend: String,
+}
+
+impl Default for StatusFormats {
+    fn default() -> Self {
+        Self {
+            idle: "IDLE {elapsed}".to_string(),
+            suppressed: "IDLE ({reason})".to_string(),
+            countdown: "SUSPEND {remaining}".to_string(),
+            maxtime: "MAXTIME {remaining}".to_string(),
+            snooze: "SNOOZE {remaining}".to_string(),
+            suspend: "SUSPEND!".to_string(),
+            active: "{procs}".to_string(),
+            would_suspend: "WOULD SUSPEND {remaining}".to_string(),
+            lock_countdown: "LOCK {remaining}".to_string(),
+            lock_suspend: "LOCK!".to_string(),
+        }
+    }
+}
+
+impl StatusFormats {
+    /// `status_format` sets every template at once; `status_format_<state>` overrides one.
+    fn from_config(configuration: &BTreeMap<String, String>) -> Self {
+        let mut formats = match configuration.get("status_format") {
+            Some(all) => Self {
+                idle: all.clone(),
+                suppressed: all.clone(),
+                countdown: all.clone(),
+                maxtime: all.clone(),
+                snooze: all.clone(),
+                suspend: all.clone(),
+                active: all.clone(),
+                would_suspend: all.clone(),
+                lock_countdown: all.clone(),
+                lock_suspend: all.clone(),
+            },
+            None => Self::default(),
+        };
+        // One set for countdown or suspend applies whatever the action
+        if let Some(countdown) = configuration.get("status_format_countdown") {
+            formats.lock_countdown = countdown.clone();
+        }
+        if let Some(suspend) = configuration.get("status_format_suspend") {
+            formats.lock_suspend = suspend.clone();
+        }
+        for (key, template) in [
+            ("status_format_idle", &mut formats.idle),
+            ("status_format_suppressed", &mut formats.suppressed),
+            ("status_format_countdown", &mut formats.countdown),
+            ("status_format_maxtime", &mut formats.maxtime),
+            ("status_format_snooze", &mut formats.snooze),
+            ("status_format_suspend", &mut formats.suspend),
+            ("status_format_active", &mut formats.active),
+            ("status_format_would_suspend", &mut formats.would_suspend),
+        ] {
+            if let Some(value) = configuration.get(key) {
+                *template = value.clone();
+            }
         }
+        formats
     }
 }
 
@@ -199,39 +1488,26 @@ register_plugin!(State);
 
 impl ZellijPlugin for State {
     fn load(&mut self, configuration: BTreeMap<String, String>) {
-        self.idle_timeout_secs = configuration
-            .get("idle_timeout_secs")
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(DEFAULT_IDLE_TIMEOUT_SECS);
-        self.countdown_secs = configuration
-            .get("countdown_secs")
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(DEFAULT_COUNTDOWN_SECS);
-        self.suspend_action = configuration
-            .get("suspend_action")
-            .cloned()
-            .unwrap_or_else(|| DEFAULT_SUSPEND_ACTION.to_string());
-        self.claude_code_idle_detection = configuration
-            .get("claude_code_idle_detection")
-            .map(|s| s.trim().eq_ignore_ascii_case("true"))
-            .unwrap_or(true);
-        self.ignore_processes = configuration
-            .get("ignore_processes")
-            .map(|s| {
-                s.split(',')
-                    .map(|p| p.trim().to_string())
-                    .filter(|p| !p.is_empty())
-                    .collect()
-            })
-            .unwrap_or_default();
-
         let ids = get_plugin_ids();
         self.zellij_pid = ids.zellij_pid;
+        self.session_start_unix = unix_now();
+        self.configure(&configuration);
+        self.config = configuration;
+
+        if !self.zellij_pid_valid() {
+            // `pgrep -P 0` (or 1) would list unrelated processes as panes
+            self.log(format!(
+                "error: zellij_pid is {}; idle detection disabled",
+                self.zellij_pid
+            ));
+        }
+        self.restore_state();
 
         request_permission(&[
             PermissionType::ReadApplicationState,
             PermissionType::RunCommands,
             PermissionType::ChangeApplicationState,
+            PermissionType::ReadCliPipes,
         ]);
 
         subscribe(&[
@@ -239,298 +1515,4898 @@ impl ZellijPlugin for State {
             EventType::PermissionRequestResult,
             EventType::RunCommandResult,
             EventType::InputReceived,
+            EventType::PaneUpdate,
+            EventType::SessionUpdate,
+            EventType::TabUpdate,
         ]);
-
-        self.log(format!(
-            "loaded config: idle_timeout={}s, countdown={}s, suspend_action={}, claude_detect={}, ignore={:?}, zellij_pid={}",
-            self.idle_timeout_secs, self.countdown_secs, self.suspend_action,
-            self.claude_code_idle_detection, self.ignore_processes, self.zellij_pid
-        ));
-        self.flush_logs();
-
-        // Check if gcloud is available
-        if self.suspend_action != "none" {
-            let mut context = BTreeMap::new();
-            context.insert("command".to_string(), "gcloud_check".to_string());
-            run_command(&["which", "gcloud"], context);
-        }
-
-        set_timeout(1.0);
+        // Everything that runs commands waits for the grant; see start()
     }
 
     fn update(&mut self, event: Event) -> bool {
-        match event {
+        // Written on each tick, and in between only when the state moves on, rather than
+        // on every key and pane update
+        let is_timer = matches!(event, Event::Timer(_));
+        let phase = self.persisted_phase();
+        let should_render = match event {
+            // A timer from before a snap back to the fast interval; the newer one ticks instead
+            Event::Timer(_) if unix_now() < self.next_tick_unix - TIMER_SLACK_SECS => false,
+            Event::Timer(_) if !self.loaded => false,
             Event::Timer(_) => {
-                if self.loaded {
-                    self.poll_count += 1;
-
-                    // Update idle elapsed time
-                    if self.is_idle {
-                        self.idle_elapsed_secs = (self.poll_count - self.last_activity_poll_count)
-                            as f64
-                            * POLL_INTERVAL_SECS;
+                self.poll_count += 1;
+                // With jitter, ticks are uneven, so the clock counts what each one waited
+                let elapsed = self.tick_elapsed_secs();
+                self.clock_secs += elapsed;
+
+                // Detect resume from suspend: poll-count math can't see the time the
+                // box spent asleep, so without this it would re-enter countdown at once.
+                let now = unix_now();
+                let gap = now - self.last_tick_unix;
+                if self.last_tick_unix > 0.0
+                    && gap > self.tick_interval_secs * RESUME_GAP_POLL_MULTIPLE
+                {
+                    self.log_event(
+                        "resumed",
+                        serde_json::json!({ "gap_secs": gap }),
+                        format!(
+                            "-> RESUMED ({}s wall-clock gap since last poll), resetting idle state",
+                            gap as u64
+                        ),
+                    );
+                    self.session_start_unix = now;
+                    self.session_extended = false;
+                    self.maxtime_countdown = false;
+                    // This tick schedules the next one, at the fast interval
+                    self.tick_interval_secs = self.poll_interval_secs;
+                    self.reset_activity();
+                    if let Some(secs) = self.savings.record_resume(now) {
+                        self.log_savings(secs);
+                        self.save_savings();
                     }
+                }
+                self.last_tick_unix = now;
 
-                    // Countdown logic
-                    if self.countdown_active {
-                        self.countdown_remaining -= POLL_INTERVAL_SECS;
-                        if self.countdown_remaining <= 0.0 {
-                            self.suspend_triggered = true;
-                            self.countdown_active = false;
-                            self.trigger_suspend();
-                        }
-                    } else if self.is_idle && self.idle_elapsed_secs >= self.idle_timeout_secs {
-                        self.countdown_active = true;
-                        self.countdown_remaining = self.countdown_secs;
+                // While snoozed, keep pushing the idle baseline forward so the full
+                // timeout applies once the snooze expires.
+                if self.is_snoozed() {
+                    self.last_activity_secs = self.clock_secs;
+                } else if self.snooze_until_secs > 0.0 {
+                    self.snooze_until_secs = 0.0;
+                    self.log("snooze expired".to_string());
+                }
+
+                self.update_idle_elapsed();
+
+                if !self.enabled && self.countdown_active {
+                    self.log("disabled, cancelling countdown".to_string());
+                    self.countdown_active = false;
+                    self.countdown_remaining = 0.0;
+                    self.maxtime_countdown = false;
+                }
+
+                // The session cap starts its own countdown, whatever the idle state
+                if self.enabled
+                    && self.max_session_secs > 0.0
+                    && !self.maxtime_countdown
+                    && !self.suspend_triggered
+                    && !self.awaiting_confirm
+                    && now - self.session_start_unix >= self.session_limit_secs()
+                {
+                    self.maxtime_countdown = true;
+                    let length = self.start_countdown();
+                    self.log_event(
+                        "maxtime_countdown",
+                        serde_json::json!({ "session_secs": now - self.session_start_unix }),
+                        format!(
+                            "-> MAXTIME COUNTDOWN (session running for {}s >= limit {}s, countdown={}s)",
+                            (now - self.session_start_unix) as u64,
+                            self.session_limit_secs() as u64,
+                            length as u64
+                        ),
+                    );
+                    self.notify(self.countdown_remaining);
+                }
+
+                self.update_timeout_profile();
+
+                if self.countdown_active && !self.maxtime_countdown && self.client_attached() {
+                    self.log("client attached, cancelling countdown".to_string());
+                    self.countdown_active = false;
+                    self.countdown_remaining = 0.0;
+                }
+
+                // Countdown logic
+                if self.countdown_active {
+                    self.countdown_remaining -= elapsed;
+                    let stage = self.countdown_stage_for(self.countdown_remaining);
+                    if stage > self.countdown_stage && self.countdown_remaining > 0.0 {
+                        self.countdown_stage = stage;
+                        self.log(format!(
+                            "countdown warning {}/{}: {}s remaining",
+                            stage,
+                            self.countdown_warnings.len(),
+                            self.countdown_remaining.max(0.0) as u64
+                        ));
+                        self.notify(self.countdown_remaining);
+                    }
+                    if !self.bell_rung
+                        && self.countdown_remaining > 0.0
+                        && self.countdown_remaining <= self.bell_at_secs
+                    {
+                        self.bell_rung = true;
+                        self.bell_pending = true;
                         self.log(format!(
-                            "-> COUNTDOWN (idle for {}s >= threshold {}s, countdown={}s)",
-                            self.idle_elapsed_secs as u64, self.idle_timeout_secs as u64, self.countdown_secs as u64
+                            "countdown bell: {}s remaining",
+                            self.countdown_remaining as u64
                         ));
                     }
+                    if self.countdown_remaining <= 0.0 && self.warning_cycle() {
+                        self.first_cycle_warned = true;
+                        self.log_event(
+                            "would_suspend",
+                            serde_json::json!({}),
+                            "warn_first_cycle: would suspend now; suspending for real from the next idle cycle"
+                                .to_string(),
+                        );
+                        self.restart_idle_cycle();
+                    } else if self.countdown_remaining <= 0.0 && self.require_confirm_suspend {
+                        self.countdown_active = false;
+                        self.awaiting_confirm = true;
+                        self.log_event(
+                            "awaiting_confirm",
+                            serde_json::json!({}),
+                            "countdown finished, waiting for zellij-idle-confirm to suspend"
+                                .to_string(),
+                        );
+                    } else if self.countdown_remaining <= 0.0 {
+                        self.suspend_triggered = true;
+                        self.countdown_active = false;
+                        if self.suspend_banner_hold_secs > 0.0 {
+                            // Leaves the banner up, and input time to cancel, before suspending
+                            self.suspend_hold_until_secs =
+                                Some(self.clock_secs + self.suspend_banner_hold_secs);
+                            self.log(format!(
+                                "countdown finished, suspending in {}s",
+                                self.suspend_banner_hold_secs
+                            ));
+                        } else {
+                            self.trigger_suspend();
+                        }
+                    }
+                } else if self.enabled
+                    && self.is_idle
+                    && !self.awaiting_confirm
+                    && self.idle_elapsed_secs >= self.idle_timeout()
+                {
+                    let reason = self.suspend_suppressed_reason();
+                    self.set_suppressed_reason(reason);
+                    if reason.is_none() {
+                        let length = self.start_countdown();
+                        self.log_event(
+                            "countdown",
+                            serde_json::json!({ "idle_timeout": self.idle_timeout() }),
+                            format!(
+                                "-> COUNTDOWN (idle for {}s >= threshold {}s, countdown={}s)",
+                                self.idle_elapsed_secs as u64,
+                                self.idle_timeout() as u64,
+                                length as u64
+                            ),
+                        );
+                        self.notify(self.countdown_remaining);
+                    }
+                }
+
+                if self.awaiting_confirm
+                    && self
+                        .confirm_input_unix
+                        .is_some_and(|at| now - at >= CONFIRM_GRACE_SECS)
+                {
+                    self.log("input received, not suspending without confirmation".to_string());
+                    self.cancel_suspend();
+                    // A capped session gets another countdown rather than none
+                    self.maxtime_countdown = false;
+                    if self.keypress_resets_idle {
+                        self.reset_activity();
+                    }
+                }
+                if self.suspend_hold_until_secs.is_some_and(|at| self.clock_secs >= at) {
+                    self.suspend_hold_until_secs = None;
+                    self.trigger_suspend();
+                }
+                if self.suspend_retry_at_unix.is_some_and(|at| now >= at) {
+                    self.suspend_retry_at_unix = None;
+                    self.run_suspend_command();
+                }
+                // The plugin's clock, unlike wall time, doesn't run while the box is suspended
+                let suspend_timed_out = self.suspend_timeout_secs > 0.0
+                    && self
+                        .suspend_started_secs
+                        .is_some_and(|at| self.clock_secs - at >= self.suspend_timeout_secs);
+                if suspend_timed_out {
+                    self.suspend_started_secs = None;
+                    // Whatever it says when it does finish no longer matters. It may still
+                    // be running, though, and a retry then runs alongside it
+                    self.suspend_run += 1;
+                    self.log_event(
+                        "suspend_timeout",
+                        serde_json::json!({ "attempt": self.suspend_attempts }),
+                        format!(
+                            "suspend command didn't report back within {}s (attempt {}), counting it as failed",
+                            self.suspend_timeout_secs, self.suspend_attempts
+                        ),
+                    );
+                    self.suspend_attempt_failed();
+                }
 
+                if self.active_window.is_some() || !self.timeout_schedule.is_empty() {
+                    self.refresh_local_time();
+                }
+                if self.idle_check_due() {
+                    self.idle_check_requested = false;
+                    self.last_idle_check_secs = self.clock_secs;
                     self.run_idle_check();
-                    self.flush_logs();
-                } else {
-                    self.loaded = true;
                 }
-                set_timeout(POLL_INTERVAL_SECS);
+                self.flush_logs();
+                let interval = self.next_poll_interval();
+                if interval != self.tick_interval_secs && self.poll_count > 0 {
+                    self.log(format!("poll interval {}s", interval));
+                }
+                self.schedule_tick(interval);
+                true
+            }
+            Event::PermissionRequestResult(PermissionStatus::Granted) => {
+                // Commands run before the grant never report back
+                self.idle_check_in_flight = false;
+                if !self.loaded {
+                    self.start();
+                }
+                true
+            }
+            Event::PermissionRequestResult(PermissionStatus::Denied) => {
+                // Without RunCommands there's no idle check, log or suspend; say so and stop
+                self.permission_denied = true;
                 true
             }
-            Event::PermissionRequestResult(_) => true,
             Event::RunCommandResult(exit_code, stdout, stderr, context) => {
                 match context.get("command").map(|s| s.as_str()) {
+                    Some("suspend")
+                        if context
+                            .get("run")
+                            .is_some_and(|run| *run != self.suspend_run.to_string()) =>
+                    {
+                        self.log(format!(
+                            "a suspend command that timed out finished after all (exit {:?}), ignoring it",
+                            exit_code
+                        ));
+                    }
                     Some("suspend") => {
+                        self.suspend_started_secs = None;
                         let out = String::from_utf8_lossy(&stdout);
                         let err = String::from_utf8_lossy(&stderr);
                         if exit_code != Some(0) {
+                            self.log_event(
+                                "suspend_failed",
+                                serde_json::json!({
+                                    "exit_code": exit_code,
+                                    "attempt": self.suspend_attempts,
+                                }),
+                                format!(
+                                    "suspend command failed (exit {:?}, attempt {}): stdout={}, stderr={}",
+                                    exit_code, self.suspend_attempts, out.trim(), err.trim()
+                                ),
+                            );
+                            let not_found = exit_code == Some(127) || err.contains("command not found");
+                            if !self.suspend_run_as.is_empty() && err.contains("sudo:") {
+                                self.log(format!(
+                                    "hint: sudo wouldn't run the suspend command as {:?}; suspend_run_as needs a NOPASSWD sudoers rule for the plugin's user",
+                                    self.suspend_run_as
+                                ));
+                            } else if not_found && self.suspend_command.is_empty() {
+                                self.log(format!(
+                                    "hint: {:?} wasn't found; if {} is installed off the plugin's PATH, set {}_path to its full path",
+                                    self.suspend_cli_path(),
+                                    self.suspend_cli(),
+                                    self.suspend_cli()
+                                ));
+                            }
+                            self.suspend_attempt_failed();
+                        } else {
+                            self.log_event(
+                                "suspend_succeeded",
+                                serde_json::json!({ "action": self.effective_suspend_action() }),
+                                format!(
+                                    "suspend command succeeded (action={}): {}",
+                                    self.effective_suspend_action(),
+                                    out.trim()
+                                ),
+                            );
+                            self.suspend_disabled_reason = None;
+                            if self.webhook_on_success {
+                                self.post_suspend_webhook();
+                            }
+                        }
+                    }
+                    Some("detect_provider") => {
+                        let provider = String::from_utf8_lossy(&stdout).trim().to_string();
+                        if provider.is_empty() || provider == "none" {
+                            self.log(
+                                "suspend_action auto: no cloud provider detected, not suspending"
+                                    .to_string(),
+                            );
+                        } else {
+                            self.log(format!("suspend_action auto: detected {}", provider));
+                        }
+                        self.detected_provider = Some(provider);
+                        self.log_suspend_unavailable();
+                        self.check_suspend_cli();
+                    }
+                    Some("cli_check") => {
+                        if exit_code != Some(0) {
+                            self.cli_missing = true;
                             self.log(format!(
-                                "suspend command failed (exit {:?}): stdout={}, stderr={}",
-                                exit_code, out.trim(), err.trim()
+                                "{} CLI not found (looked for {:?}); set {}_path if it's installed off PATH",
+                                self.suspend_cli(),
+                                self.suspend_cli_path(),
+                                self.suspend_cli()
                             ));
-                        } else {
-                            self.log(format!("suspend command succeeded: {}", out.trim()));
+                            self.log_suspend_unavailable();
                         }
                     }
-                    Some("gcloud_check") => {
+                    Some("fallback") => {
                         if exit_code != Some(0) {
-                            self.gcloud_missing = true;
-                            self.log("gcloud CLI not found on PATH".to_string());
+                            self.log(format!(
+                                "fallback_action command failed (exit {:?}): stdout={}, stderr={}",
+                                exit_code,
+                                String::from_utf8_lossy(&stdout).trim(),
+                                String::from_utf8_lossy(&stderr).trim()
+                            ));
                         }
+                        self.restart_idle_cycle();
                     }
-                    Some("log") => {} // ignore log flush results
-                    _ => {
-                        self.parse_idle_check_output(&stdout);
+                    Some("pre_suspend") => {
+                        if !self.suspend_triggered {
+                            // Activity reset the suspend while the hook was running
+                            self.log(
+                                "pre_suspend_command finished after suspend was cancelled"
+                                    .to_string(),
+                            );
+                        } else if exit_code == Some(0) {
+                            self.log("pre_suspend_command succeeded, suspending".to_string());
+                            self.run_suspend_command();
+                        } else {
+                            self.log(format!(
+                                "pre_suspend_command vetoed suspend (exit {:?}): stdout={}, stderr={}",
+                                exit_code,
+                                String::from_utf8_lossy(&stdout).trim(),
+                                String::from_utf8_lossy(&stderr).trim()
+                            ));
+                            self.suspend_command_sent = false;
+                            self.suspend_triggered = false;
+                            // A capped session gets another countdown rather than none
+                            self.maxtime_countdown = false;
+                        }
+                    }
+                    Some("dump_layout") => {
+                        if exit_code == Some(0) {
+                            self.log(format!(
+                                "session layout saved to {}",
+                                String::from_utf8_lossy(&stdout).trim()
+                            ));
+                        } else {
+                            self.log(format!(
+                                "dump-layout failed (exit {:?}), suspending anyway: {}",
+                                exit_code,
+                                String::from_utf8_lossy(&stderr).trim()
+                            ));
+                        }
+                        if self.suspend_triggered {
+                            self.run_pre_suspend_command();
+                        } else {
+                            self.log("dump-layout finished after suspend was cancelled".to_string());
+                        }
+                    }
+                    Some("selfcheck") => {
+                        let report = self.selfcheck_report(&String::from_utf8_lossy(&stdout));
+                        for line in &report {
+                            self.log(format!("selfcheck: {}", line));
+                        }
+                        for pipe_id in std::mem::take(&mut self.selfcheck_replies) {
+                            self.reply(&PipeSource::Cli(pipe_id), &report.join("\n"));
+                        }
+                    }
+                    Some("reload") => {
+                        let path = context.get("path").cloned().unwrap_or_default();
+                        let reply = if exit_code == Some(0) {
+                            self.reload_config(&String::from_utf8_lossy(&stdout))
+                        } else {
+                            let err = format!(
+                                "zellij-idle-reload: couldn't read {}: {}",
+                                path,
+                                String::from_utf8_lossy(&stderr).trim()
+                            );
+                            self.log(err.clone());
+                            err
+                        };
+                        for pipe_id in std::mem::take(&mut self.reload_replies) {
+                            self.reply(&PipeSource::Cli(pipe_id), &reply);
+                        }
+                    }
+                    Some("savings_read") => {
+                        let out = String::from_utf8_lossy(&stdout);
+                        let pending = std::mem::replace(&mut self.savings, Savings::parse(&out));
+                        // Anything counted before the file was first read goes on top
+                        let pending = if self.savings_loaded { Savings::default() } else { pending };
+                        self.savings_loaded = true;
+                        let resumed = self.savings.suspended_at_unix > 0.0;
+                        // Still marked suspended: the box was stopped rather than suspended,
+                        // and was down from then until it booted
+                        let boot = out
+                            .lines()
+                            .find_map(|line| line.strip_prefix("boot_unix="))
+                            .and_then(|v| v.trim().parse::<f64>().ok())
+                            .filter(|boot| *boot > self.savings.suspended_at_unix);
+                        let resume = self.savings.record_resume(boot.unwrap_or_else(unix_now));
+                        if let Some(secs) = resume {
+                            self.log_savings(secs);
+                        }
+                        let had_pending = pending != Savings::default();
+                        self.savings.merge(pending);
+                        if resume.is_some() || had_pending {
+                            self.save_savings();
+                        }
+                        if resumed {
+                            self.run_post_resume_command();
+                        }
+                    }
+                    Some("post_resume") => {
+                        if exit_code == Some(0) {
+                            self.log("post_resume_command succeeded".to_string());
+                        } else {
+                            self.log(format!(
+                                "post_resume_command failed (exit {:?}): stdout={}, stderr={}",
+                                exit_code,
+                                String::from_utf8_lossy(&stdout).trim(),
+                                String::from_utf8_lossy(&stderr).trim()
+                            ));
+                        }
+                    }
+                    Some("savings_write") => {
+                        if exit_code != Some(0) {
+                            self.log(format!(
+                                "failed to write the savings file: {}",
+                                String::from_utf8_lossy(&stderr).trim()
+                            ));
+                        }
+                    }
+                    Some("heartbeat") => {
+                        // Logged once per stretch of failures rather than every poll
+                        let failed = exit_code != Some(0);
+                        if failed && !self.heartbeat_failed {
+                            self.log(format!(
+                                "failed to write heartbeat_file {:?}: {}",
+                                self.heartbeat_file,
+                                String::from_utf8_lossy(&stderr).trim()
+                            ));
+                        }
+                        self.heartbeat_failed = failed;
+                    }
+                    Some("lock") => {
+                        if exit_code != Some(0) {
+                            self.log(format!(
+                                "lock_command failed (exit {:?}): stdout={}, stderr={}",
+                                exit_code,
+                                String::from_utf8_lossy(&stdout).trim(),
+                                String::from_utf8_lossy(&stderr).trim()
+                            ));
+                        }
+                        self.restart_idle_cycle();
+                    }
+                    Some("find_layout") => {
+                        let found = String::from_utf8_lossy(&stdout).trim().to_string();
+                        if !found.is_empty() {
+                            self.log(format!(
+                                "layout saved before the last suspend: {}; restore it with `zellij --layout <file>`",
+                                found
+                            ));
+                        }
+                    }
+                    Some("post_suspend") => {
+                        if exit_code != Some(0) {
+                            self.log(format!(
+                                "post_suspend_webhook failed (exit {:?}): stdout={}, stderr={}",
+                                exit_code,
+                                String::from_utf8_lossy(&stdout).trim(),
+                                String::from_utf8_lossy(&stderr).trim()
+                            ));
+                        }
+                    }
+                    Some("notify") => {
+                        if exit_code != Some(0) {
+                            self.log(format!(
+                                "notify command failed (exit {:?}): stdout={}, stderr={}",
+                                exit_code,
+                                String::from_utf8_lossy(&stdout).trim(),
+                                String::from_utf8_lossy(&stderr).trim()
+                            ));
+                        }
+                    }
+                    Some("platform") => {
+                        let platform = String::from_utf8_lossy(&stdout).trim().to_string();
+                        self.platform_macos = platform == "Darwin";
+                        self.log(format!("platform: {}", platform));
+                        // The selfcheck's idle scan needs to know which dump script to use
+                        if self.selfcheck_on_load {
+                            self.run_selfcheck();
+                        }
+                    }
+                    Some("uptime") => {
+                        let out = String::from_utf8_lossy(&stdout);
+                        let uptime = out
+                            .split_whitespace()
+                            .next()
+                            .and_then(|s| s.parse::<f64>().ok());
+                        match uptime {
+                            Some(uptime) if exit_code == Some(0) => {
+                                self.boot_unix = Some(unix_now() - uptime);
+                            }
+                            _ => self.log(
+                                "could not read /proc/uptime; min_uptime_secs has no effect"
+                                    .to_string(),
+                            ),
+                        }
+                    }
+                    Some("clock") => {
+                        // `<ISO weekday, Monday = 1> <HH:MM>`
+                        let out = String::from_utf8_lossy(&stdout);
+                        let mut fields = out.split_whitespace();
+                        self.local_weekday = fields
+                            .next()
+                            .and_then(|d| d.parse::<usize>().ok())
+                            .filter(|d| (1..=7).contains(d))
+                            .map(|d| d - 1);
+                        self.local_time_minutes = fields.next().and_then(parse_hhmm);
+                    }
+                    Some("log") => {} // ignore log flush results
+                    Some("idle_check") => self.handle_idle_check_result(exit_code, &stdout, &stderr),
+                    other => self.log(format!("ignoring the result of unknown command {:?}", other)),
+                }
+                true
+            }
+            Event::InputReceived => self.handle_input(),
+            Event::PaneUpdate(manifest) => {
+                let panes = count_terminal_panes(&manifest);
+                if self.zellij_pane_count != Some(panes) {
+                    self.log(format!(
+                        "zellij reports {} terminal panes ({} shells found by the last idle check)",
+                        panes, self.shell_count
+                    ));
+                    // A new pane's shell isn't in the cache
+                    self.child_pids = None;
+                }
+                self.zellij_pane_count = Some(panes);
+                self.pane_tabs = manifest
+                    .panes
+                    .iter()
+                    .flat_map(|(tab, panes)| {
+                        panes
+                            .iter()
+                            .filter(|pane| !pane.is_plugin)
+                            .map(move |pane| (pane.id, *tab))
+                    })
+                    .collect();
+                self.idle_check_requested = true;
+                false
+            }
+            Event::SessionUpdate(sessions, _) => {
+                let current = sessions.iter().find(|session| session.is_current_session);
+                let clients = current.map(|session| session.connected_clients);
+                if let Some(session) = current {
+                    self.session_name = Some(session.name.clone());
+                }
+                if clients.is_some() && clients != self.connected_clients {
+                    self.log(format!("{} clients attached", clients.unwrap_or(0)));
+                    self.connected_clients = clients;
+                }
+                self.idle_check_requested = true;
+                false
+            }
+            Event::TabUpdate(tabs) => {
+                self.tab_names = tabs
+                    .into_iter()
+                    .map(|tab| (tab.position, tab.name))
+                    .collect();
+                self.idle_check_requested = true;
+                false
+            }
+            _ => false,
+        };
+        if self.loaded && (is_timer || self.persisted_phase() != phase) {
+            self.save_state();
+        }
+        should_render
+    }
+
+    fn pipe(&mut self, pipe_message: PipeMessage) -> bool {
+        match pipe_message.name.as_str() {
+            // `zellij pipe --name zellij-idle-snooze [-- <secs>]`; the payload may also be a
+            // duration like `10m`, as are those of extend and keepalive
+            "zellij-idle-snooze" => {
+                let secs = pipe_message
+                    .payload
+                    .as_deref()
+                    .and_then(parse_duration)
+                    .unwrap_or(self.snooze_secs);
+                self.snooze(secs);
+                self.flush_logs();
+                true
+            }
+            // `zellij pipe --name zellij-idle-extend [-- <secs>]` adds extend_secs to a
+            // running countdown, or to the idle time left before one starts
+            "zellij-idle-extend" => {
+                let secs = pipe_message
+                    .payload
+                    .as_deref()
+                    .and_then(parse_duration)
+                    .filter(|secs| *secs > 0.0)
+                    .unwrap_or(self.extend_secs);
+                let reply = self.extend(secs);
+                self.reply(&pipe_message.source, &reply);
+                self.flush_logs();
+                true
+            }
+            // `zellij pipe --name zellij-idle-keepalive [-- <secs>]` counts as activity, like
+            // a keypress; with a payload it also holds off idle for that long, like a snooze
+            "zellij-idle-keepalive" => {
+                let secs = pipe_message.payload.as_deref().and_then(parse_duration);
+                match secs {
+                    Some(secs) => {
+                        self.log(format!("keepalive received, holding for {}s", secs as u64));
+                        self.snooze(secs);
+                    }
+                    None => {
+                        self.log("keepalive received, resetting idle timer".to_string());
+                        self.reset_activity();
                     }
                 }
-                true
-            }
-            Event::InputReceived => {
-                if self.countdown_active {
-                    self.log("input received, cancelling countdown".to_string());
-                } else if self.is_idle {
-                    self.log("input received, resetting idle timer".to_string());
-                }
-                self.last_activity_poll_count = self.poll_count;
-                self.idle_elapsed_secs = 0.0;
-                self.is_idle = false;
+                self.flush_logs();
+                true
+            }
+            // `zellij pipe --name zellij-idle-suspend-now` skips the idle timeout and countdown
+            "zellij-idle-suspend-now" => {
+                self.log("suspend requested via zellij-idle-suspend-now".to_string());
                 self.countdown_active = false;
-                self.countdown_remaining = 0.0;
-                self.suspend_triggered = false;
-                self.suspend_command_sent = false;
+                self.awaiting_confirm = false;
+                self.confirm_input_unix = None;
+                self.suspend_triggered = true;
+                self.trigger_suspend();
+                self.flush_logs();
+                true
+            }
+            // `zellij pipe --name zellij-idle-config -- 'enabled=false'` overrides config
+            // keys from layout.kdl, one `key=value` per line, until the plugin reloads
+            "zellij-idle-config" => {
+                let payload = pipe_message.payload.unwrap_or_default();
+                let updates = self.parse_config_lines("zellij-idle-config", &payload);
+                let changed: Vec<String> = updates.keys().cloned().collect();
+                self.config.extend(updates);
+                self.log(format!("config updated via zellij-idle-config: {}", changed.join(", ")));
+                self.reconfigure(&changed);
+                self.reply(&pipe_message.source, &format!("updated: {}", changed.join(", ")));
+                self.flush_logs();
+                true
+            }
+            // `zellij pipe --name zellij-idle-reload -- "$(cat idle.conf)"` replaces the whole
+            // config, so keys left out go back to their defaults; a payload of just a path
+            // reads the `key=value` lines from that file instead
+            "zellij-idle-reload" => {
+                let payload = pipe_message.payload.unwrap_or_default();
+                let path = payload.trim();
+                // The plugin's working directory is zellij's, so a relative path would be too
+                let one_line = !path.contains('=') && !path.contains('\n');
+                if one_line && (path.starts_with('/') || path.starts_with("~/")) {
+                    if let PipeSource::Cli(pipe_id) = &pipe_message.source {
+                        self.reload_replies.push(pipe_id.clone());
+                    }
+                    let mut context = BTreeMap::new();
+                    context.insert("command".to_string(), "reload".to_string());
+                    context.insert("path".to_string(), path.to_string());
+                    run_command(&["bash", "-c", READ_CONFIG_SCRIPT, "_", path], context);
+                    return false;
+                }
+                let reply = if one_line && !path.is_empty() && !path.starts_with('#') {
+                    let err = format!(
+                        "zellij-idle-reload: {:?} is neither key=value lines nor an absolute or ~/ path, not reloading",
+                        path
+                    );
+                    self.log(err.clone());
+                    err
+                } else {
+                    self.reload_config(&payload)
+                };
+                self.reply(&pipe_message.source, &reply);
+                self.flush_logs();
+                true
+            }
+            // `zellij pipe --name zellij-idle-selfcheck` checks the suspend CLI, its
+            // credentials and metadata server, and idle detection, and prints the results
+            "zellij-idle-selfcheck" => {
+                if let PipeSource::Cli(pipe_id) = &pipe_message.source {
+                    self.selfcheck_replies.push(pipe_id.clone());
+                }
+                self.run_selfcheck();
+                self.flush_logs();
+                false
+            }
+            // `zellij pipe --name zellij-idle-confirm` lets a countdown that ran out under
+            // require_confirm_suspend go ahead and suspend
+            "zellij-idle-confirm" => {
+                let reply = if self.awaiting_confirm {
+                    self.log("suspend confirmed via zellij-idle-confirm".to_string());
+                    self.awaiting_confirm = false;
+                    self.confirm_input_unix = None;
+                    self.suspend_triggered = true;
+                    self.trigger_suspend();
+                    "suspending"
+                } else {
+                    "nothing to confirm"
+                };
+                self.reply(&pipe_message.source, reply);
+                self.flush_logs();
                 true
             }
+            // `zellij pipe --name zellij-idle-query` prints the current state as JSON
+            "zellij-idle-query" => {
+                self.reply(&pipe_message.source, &self.state_json());
+                false
+            }
             _ => false,
         }
     }
 
     fn render(&mut self, _rows: usize, cols: usize) {
-        if !self.loaded {
-            print!("loading");
+        if self.loaded
+            && !self.config_errors.is_empty()
+            && self.config_err_renders < CONFIG_ERR_RENDERS
+        {
+            self.config_err_renders += 1;
+            let msg = format!("CONFIG ERR {}", self.config_errors[0]);
+            print!("{}", self.banner("31;1", &msg, "!", cols));
             return;
         }
+        if std::mem::take(&mut self.bell_pending) {
+            print!("\x07");
+        }
+        print!("{}", self.status_line(cols));
+    }
+}
 
-        if self.gcloud_missing {
-            let msg = "!gcloud";
-            let truncated = &msg[..msg.len().min(cols)];
-            let padding = cols.saturating_sub(truncated.len());
-            print!(
-                "\x1b[31;1m{}{}\x1b[0m",
-                truncated,
-                " ".repeat(padding)
+impl State {
+    /// Runs once permissions are granted: the startup probes, then the first idle check
+    /// and the poll timer.
+    fn start(&mut self) {
+        self.loaded = true;
+        self.fetch_uptime();
+        if self.dump_layout_before_suspend {
+            let mut context = BTreeMap::new();
+            context.insert("command".to_string(), "find_layout".to_string());
+            run_command(
+                &["bash", "-c", FIND_LAYOUT_DUMP_SCRIPT, "_", &self.layout_dump_path],
+                context,
             );
-            return;
         }
 
+        let mut context = BTreeMap::new();
+        context.insert("command".to_string(), "platform".to_string());
+        run_command(&["uname", "-s"], context);
+
+        self.check_suspend_cli();
+
+        let mut context = BTreeMap::new();
+        context.insert("command".to_string(), "savings_read".to_string());
+        run_command(&["bash", "-c", SAVINGS_FILE_SCRIPT, "_", "read"], context);
+
+        self.last_idle_check_secs = self.clock_secs;
+        self.run_idle_check();
+        self.flush_logs();
+        self.schedule_tick(self.poll_interval_secs);
+    }
+
+    /// Which state the bar shows, as for the `{state}` placeholder.
+    fn state_name(&self) -> &'static str {
         if self.suspend_triggered {
-            let msg = "SUSPEND!";
-            let truncated = &msg[..msg.len().min(cols)];
-            let padding = cols.saturating_sub(truncated.len());
-            print!(
-                "\x1b[41;97;1m{}{}\x1b[0m",
-                truncated,
-                " ".repeat(padding)
-            );
+            "suspend"
+        } else if self.countdown_active && self.maxtime_countdown {
+            "maxtime"
         } else if self.countdown_active {
-            let remaining = self.countdown_remaining.max(0.0) as u64;
-            let msg = format!("SUSPEND {}s", remaining);
-            let truncated = &msg[..msg.len().min(cols)];
-            let padding = cols.saturating_sub(truncated.len());
-            print!(
-                "\x1b[43;30;1m{}{}\x1b[0m",
-                truncated,
-                " ".repeat(padding)
-            );
+            "countdown"
+        } else if self.is_snoozed() {
+            "snooze"
         } else if self.is_idle {
-            let elapsed = self.idle_elapsed_secs as u64;
-            let msg = format!("IDLE {}s", elapsed);
-            let truncated = &msg[..msg.len().min(cols)];
-            let padding = cols.saturating_sub(truncated.len());
-            print!("\x1b[32m{}{}\x1b[0m", truncated, " ".repeat(padding));
-        } else {
-            let procs = if self.active_processes.is_empty() {
-                "...".to_string()
-            } else {
-                let joined = self.active_processes.join(",");
-                if joined.len() > cols {
-                    format!("{}+", &joined[..cols.saturating_sub(1)])
-                } else {
-                    joined
-                }
-            };
-            let padding = cols.saturating_sub(procs.len());
-            print!("\x1b[34m{}{}\x1b[0m", procs, " ".repeat(padding));
+            "idle"
+        } else {
+            "active"
         }
     }
-}
 
-impl State {
-    fn log(&mut self, msg: String) {
-        eprintln!("zellij-idle: {}", msg);
-        self.log_buffer.push(msg);
-    }
+    /// Builds the single-line status bar text, including ANSI styling, padded to `cols`.
+    fn status_line(&self, cols: usize) -> String {
+        if self.permission_denied {
+            return self.banner("31;1", "NO PERMS", "!", cols);
+        }
+        if !self.loaded {
+            if cols < NARROW_STATUS_COLS {
+                return pad_to_width("…", cols);
+            }
+            return "loading".to_string();
+        }
 
-    fn flush_logs(&mut self) {
-        if self.log_buffer.is_empty() {
-            return;
+        if !self.zellij_pid_valid() {
+            let msg = format!("BAD PID {}", self.zellij_pid);
+            return self.banner("31;1", &msg, "!", cols);
         }
-        let content = self.log_buffer.join("\n");
-        self.log_buffer.clear();
-        let mut context = BTreeMap::new();
-        context.insert("command".to_string(), "log".to_string());
-        run_command(
-            &["bash", "-c", LOG_FLUSH_SCRIPT, "_", &content],
-            context,
-        );
-    }
 
-    fn run_idle_check(&self) {
-        let pid_str = self.zellij_pid.to_string();
-        let claude_detect = if self.claude_code_idle_detection {
-            "true"
+        if self.bad_suspend_action.is_some() {
+            return self.banner("31;1", "BAD ACTION", "!", cols);
+        }
+
+        if self.cli_missing {
+            let msg = format!("!{}", self.suspend_cli());
+            return self.banner("31;1", &msg, "!", cols);
+        }
+
+        if !self.enabled {
+            return self.banner("90", "DISABLED", "-", cols);
+        }
+
+        if self.empty_poll_streak >= NO_PANES_POLLS
+            && !self.countdown_active
+            && !self.suspend_triggered
+        {
+            return self.banner("33;1", "NO PANES", "!", cols);
+        }
+
+        if self.awaiting_confirm {
+            return self.banner(&self.color_suspend, "CONFIRM SUSPEND", "?", cols);
+        }
+
+        // A later idle cycle tries again, and its countdown shows as usual
+        if self.suspend_disabled_reason.is_some()
+            && !self.countdown_active
+            && !self.suspend_triggered
+        {
+            return self.banner("31;1", "SUSPEND FAILED", "!", cols);
+        }
+
+        let formats = &self.status_formats;
+        let state = self.state_name();
+        // Could be any step of a suspend chain
+        let locking = self.effective_suspend_action() == "lock";
+        let (template, color) = match state {
+            "suspend" if locking => (&formats.lock_suspend, self.color_suspend.as_str()),
+            "suspend" => (&formats.suspend, self.color_suspend.as_str()),
+            "maxtime" | "countdown" if self.warning_cycle() => {
+                (&formats.would_suspend, self.countdown_color())
+            }
+            "maxtime" => (&formats.maxtime, self.countdown_color()),
+            "countdown" if locking => (&formats.lock_countdown, self.countdown_color()),
+            "countdown" => (&formats.countdown, self.countdown_color()),
+            "snooze" => (&formats.snooze, self.color_snooze.as_str()),
+            "idle" => match self.suppressed_reason {
+                Some(_) if self.idle_elapsed_secs >= self.idle_timeout() => {
+                    (&formats.suppressed, self.color_idle.as_str())
+                }
+                _ => (&formats.idle, self.color_idle.as_str()),
+            },
+            _ => (&formats.active, self.color_active.as_str()),
+        };
+        // Inverted video for the last bell_at_secs of the countdown
+        let color = if self.countdown_active && self.countdown_remaining <= self.bell_at_secs {
+            format!("{};7", color)
         } else {
-            "false"
+            color.to_string()
         };
-        let ignore_procs = self.ignore_processes.join(",");
-        let mut context = BTreeMap::new();
-        context.insert("command".to_string(), "idle_check".to_string());
-        run_command(
+        if cols < NARROW_STATUS_COLS {
+            let glyph = match state {
+                "suspend" => "S",
+                "maxtime" => "M",
+                "countdown" => "C",
+                "snooze" => "Z",
+                "idle" => "I",
+                _ => "A",
+            };
+            return self.banner(&color, "", glyph, cols);
+        }
+
+        let remaining = if self.countdown_active {
+            let remaining = self.countdown_remaining.max(0.0) as u64;
+            if self.countdown_mmss {
+                format_mmss(remaining)
+            } else {
+                format!("{}s", remaining)
+            }
+        } else if self.is_snoozed() {
+            format_short_duration(self.snooze_until_secs - self.clock_secs)
+        } else if self.is_idle && self.show_arming_progress {
+            // Time left before the countdown starts
+            let remaining = (self.idle_timeout() - self.idle_elapsed_secs).max(0.0) as u64;
+            if remaining >= 60 {
+                format_minutes_secs(remaining)
+            } else {
+                format!("{}s", remaining)
+            }
+        } else {
+            String::new()
+        };
+        let elapsed = self.idle_elapsed_secs as u64;
+        let elapsed = if self.countdown_mmss && elapsed >= 60 {
+            format_minutes_secs(elapsed)
+        } else {
+            format!("{}s", elapsed)
+        };
+        let procs = if self.active_processes.is_empty() {
+            "...".to_string()
+        } else {
+            self.active_processes.join(",")
+        };
+        let msg = fill_template(
+            template,
             &[
-                "bash",
-                "-c",
-                IDLE_CHECK_SCRIPT,
-                "_",
-                &pid_str,
-                claude_detect,
-                &ignore_procs,
+                ("state", state),
+                ("elapsed", &elapsed),
+                ("remaining", &remaining),
+                (
+                    "reason",
+                    if state == "active" {
+                        self.active_reason.as_deref().unwrap_or("...")
+                    } else {
+                        self.suppressed_reason.unwrap_or("")
+                    },
+                ),
+                ("procs", &procs),
+                ("count", &self.active_pane_count.to_string()),
+                ("panes", &self.pane_total().to_string()),
+                ("profile", self.timeout_profile.as_deref().unwrap_or("default")),
             ],
-            context,
         );
+        let msg = match self.extend_flash {
+            Some((secs, until)) if unix_now() < until => {
+                format!("+{} {}", format_short_duration(secs), msg)
+            }
+            _ => msg,
+        };
+        let sparkline = self.sparkline();
+        let sparkline_cols = sparkline.chars().count() + 1;
+        let text_cols = if self.show_sparkline && cols >= sparkline_cols + SPARKLINE_MIN_TEXT_COLS {
+            cols - sparkline_cols
+        } else {
+            cols
+        };
+        // The process list can run long, so mark where it was cut off
+        let msg = if state == "active" && msg.chars().count() > text_cols {
+            format!("{}+", truncate_chars(&msg, text_cols.saturating_sub(1)))
+        } else {
+            msg
+        };
+        let msg = if text_cols < cols {
+            format!("{} {}", pad_to_width(&msg, text_cols), sparkline)
+        } else {
+            msg
+        };
+        self.paint(&color, &pad_to_width(&msg, cols))
     }
 
-    fn trigger_suspend(&mut self) {
-        if self.suspend_command_sent {
-            return;
-        }
-        self.suspend_command_sent = true;
+    /// The recent polls oldest first, `█` for one that kept the box awake and `▁` otherwise.
+    fn sparkline(&self) -> String {
+        self.activity_history
+            .iter()
+            .map(|&active| if active { '█' } else { '▁' })
+            .collect()
+    }
 
-        if self.suspend_action == "none" {
-            self.log("suspend_action is 'none', skipping gcloud command".to_string());
-            return;
+    /// `text` padded to `cols` in `color`, or just `glyph` on a bar too narrow for text.
+    /// On a zero-width bar there's nothing to paint, so not even the color is sent.
+    fn banner(&self, color: &str, text: &str, glyph: &str, cols: usize) -> String {
+        if cols == 0 {
+            String::new()
+        } else if cols < NARROW_STATUS_COLS {
+            self.paint(color, &pad_to_width(glyph, cols))
+        } else {
+            self.paint(color, &pad_to_width(text, cols))
         }
+    }
 
-        let action = match self.suspend_action.as_str() {
-            "stop" => "stop",
-            _ => "suspend",
-        };
-
-        self.log(format!("triggering suspend (action={})", action));
-        let mut context = BTreeMap::new();
-        context.insert("command".to_string(), "suspend".to_string());
-        run_command(&["bash", "-c", SUSPEND_SCRIPT, "_", action], context);
+    /// Wraps `text` in the SGR `color`, or leaves it plain with no_color.
+    fn paint(&self, color: &str, text: &str) -> String {
+        if self.no_color {
+            text.to_string()
+        } else {
+            format!("\x1b[{}m{}\x1b[0m", color, text)
+        }
     }
 
-    fn parse_idle_check_output(&mut self, stdout: &[u8]) {
-        let output = String::from_utf8_lossy(stdout);
-        let mut active_count = 0;
-        let mut active_procs = Vec::new();
-        let mut idle_details = Vec::new();
-        let mut active_details = Vec::new();
-        let mut total_panes = 0;
+    /// Applies the plugin configuration, at load and again for each zellij-idle-config
+    /// pipe message. Every setting is recomputed from `configuration`.
+    fn configure(&mut self, configuration: &BTreeMap<String, String>) {
+        self.ignore_patterns.clear();
+        self.active_window = None;
+        self.countdown_mmss = false;
+        self.color_idle = DEFAULT_COLOR_IDLE.to_string();
+        self.color_countdown = DEFAULT_COLOR_COUNTDOWN.to_string();
+        self.color_warning = DEFAULT_COLOR_WARNING.to_string();
+        self.color_suspend = DEFAULT_COLOR_SUSPEND.to_string();
+        self.color_snooze = DEFAULT_COLOR_SNOOZE.to_string();
+        self.color_active = DEFAULT_COLOR_ACTIVE.to_string();
 
-        for line in output.lines() {
-            let line = line.trim();
-            if line.is_empty() {
-                continue;
-            }
-            total_panes += 1;
+        self.config_errors.clear();
+        self.config_err_renders = 0;
 
-            let parts: Vec<&str> = line.splitn(3, ':').collect();
-            if parts.len() < 3 {
-                continue;
+        self.enabled = config_bool(configuration, "enabled", &mut self.config_errors)
+            .unwrap_or(true);
+        self.log_file = configuration
+            .get("log_file")
+            .map(|s| s.trim().to_string())
+            .unwrap_or_default();
+        self.heartbeat_file = configuration
+            .get("heartbeat_file")
+            .map(|s| s.trim().to_string())
+            .unwrap_or_default();
+        self.log_max_bytes = config_num(configuration, "log_max_bytes", &mut self.config_errors)
+            .unwrap_or(DEFAULT_LOG_MAX_BYTES);
+        self.log_json = match configuration.get("log_format").map(|s| s.trim()) {
+            None | Some("text") => false,
+            Some("json") => true,
+            Some(other) => {
+                self.config_errors
+                    .push(format!("log_format: unknown {:?}, using text", other));
+                false
             }
-
-            if parts[0] == "active" {
-                active_count += 1;
-                let proc_name = parts[2].trim();
-                active_details.push(format!("pid={} fg={}", parts[1], proc_name));
-                if !proc_name.is_empty() && proc_name != "unknown" {
-                    active_procs.push(proc_name.to_string());
+        };
+        self.poll_interval_secs = config_secs(configuration, "poll_interval_secs", &mut self.config_errors)
+            .filter(|secs: &f64| *secs > 0.0)
+            .unwrap_or(DEFAULT_POLL_INTERVAL_SECS);
+        self.max_poll_interval_secs = config_secs(configuration, "max_poll_interval_secs", &mut self.config_errors)
+            .unwrap_or(DEFAULT_MAX_POLL_INTERVAL_SECS);
+        self.poll_backoff_after_secs = config_secs(configuration, "poll_backoff_after_secs", &mut self.config_errors)
+            .unwrap_or(DEFAULT_POLL_BACKOFF_AFTER_SECS);
+        self.poll_jitter_secs = config_secs(configuration, "poll_jitter_secs", &mut self.config_errors)
+            .unwrap_or(0.0);
+        // Any more and a late poll could pass for a resume from suspend
+        let max_jitter = self.poll_interval_secs / 2.0;
+        if self.poll_jitter_secs > max_jitter {
+            self.log(format!(
+                "warning: poll_jitter_secs {} is over half of poll_interval_secs, using {}",
+                self.poll_jitter_secs, max_jitter
+            ));
+            self.poll_jitter_secs = max_jitter;
+        }
+        self.idle_timeout_secs = config_secs(configuration, "idle_timeout_secs", &mut self.config_errors)
+            .unwrap_or(DEFAULT_IDLE_TIMEOUT_SECS);
+        self.timeout_schedule = match configuration.get("timeout_schedule") {
+            Some(spec) => match schedule::parse_timeout_schedule(spec) {
+                Ok(rules) => rules,
+                Err(e) => {
+                    self.config_errors
+                        .push(format!("timeout_schedule: {}, ignoring it", e));
+                    Vec::new()
                 }
-            } else {
-                idle_details.push(format!("pid={} {}", parts[1], parts[2].trim()));
+            },
+            None => Vec::new(),
+        };
+        self.countdown_secs = config_secs(configuration, "countdown_secs", &mut self.config_errors)
+            .unwrap_or(DEFAULT_COUNTDOWN_SECS);
+        self.countdown_warnings = Vec::new();
+        let warnings = configuration.get("countdown_warnings").map(String::as_str).unwrap_or("");
+        for warning in warnings.split(',').map(str::trim).filter(|w| !w.is_empty()) {
+            match parse_duration(warning) {
+                Some(secs) if secs > 0.0 => self.countdown_warnings.push(secs),
+                Some(_) => {}
+                None => self.config_errors.push(format!(
+                    "countdown_warnings: can't parse {:?}, leaving it out",
+                    warning
+                )),
             }
         }
-
-        self.log(format!(
-            "poll #{}: {}/{} panes active | active=[{}] idle=[{}]",
-            self.poll_count,
-            active_count,
-            total_panes,
-            active_details.join(", "),
-            idle_details.join(", ")
-        ));
-
-        let was_idle = self.is_idle;
-        self.active_pane_count = active_count;
-        self.active_processes = active_procs;
-
-        if active_count == 0 && total_panes > 0 {
-            if !self.is_idle {
-                self.is_idle = true;
-                self.log(format!("-> IDLE (all {} panes idle)", total_panes));
+        self.countdown_warnings.sort_by(|a, b| b.total_cmp(a));
+        self.countdown_warnings.dedup();
+        self.bell_at_secs = config_secs(configuration, "bell_at_secs", &mut self.config_errors)
+            .unwrap_or(0.0);
+        self.suspend_banner_hold_secs =
+            config_secs(configuration, "suspend_banner_hold_secs", &mut self.config_errors)
+                .unwrap_or(0.0);
+        self.require_confirm_suspend =
+            config_bool(configuration, "require_confirm_suspend", &mut self.config_errors)
+                .unwrap_or(false);
+        match configuration.get("countdown_format").map(|s| s.trim()) {
+            None | Some("seconds") => {}
+            Some("mmss") => self.countdown_mmss = true,
+            Some(other) => self.config_errors.push(format!(
+                "countdown_format: unknown {:?}; expected seconds or mmss",
+                other
+            )),
+        }
+        self.status_formats = StatusFormats::from_config(configuration);
+        self.show_active_reason = config_bool(configuration, "show_active_reason", &mut self.config_errors)
+            .unwrap_or(false);
+        let custom_active_format = configuration.contains_key("status_format")
+            || configuration.contains_key("status_format_active");
+        if self.show_active_reason && !custom_active_format {
+            self.status_formats.active = "ACTIVE: {reason}".to_string();
+        }
+        self.show_arming_progress =
+            config_bool(configuration, "show_arming_progress", &mut self.config_errors)
+                .unwrap_or(false);
+        self.show_sparkline = config_bool(configuration, "show_sparkline", &mut self.config_errors)
+            .unwrap_or(false);
+        let custom_idle_format = configuration.contains_key("status_format")
+            || configuration.contains_key("status_format_idle");
+        if self.show_arming_progress && !custom_idle_format {
+            self.status_formats.idle = "IDLE→{remaining}".to_string();
+        }
+        let mut bad_colors = Vec::new();
+        for (key, color) in [
+            ("color_idle", &mut self.color_idle),
+            ("color_countdown", &mut self.color_countdown),
+            ("color_warning", &mut self.color_warning),
+            ("color_suspend", &mut self.color_suspend),
+            ("color_snooze", &mut self.color_snooze),
+            ("color_active", &mut self.color_active),
+        ] {
+            if let Some(value) = configuration.get(key) {
+                match parse_color(value) {
+                    Some(sgr) => *color = sgr,
+                    None => bad_colors.push(format!(
+                        "{}: invalid {:?}; expected a color name or SGR codes like \"41;97;1\"",
+                        key, value
+                    )),
+                }
             }
-        } else if active_count > 0 {
-            if was_idle || self.countdown_active {
-                self.log(format!(
-                    "-> ACTIVE (keeping awake: {})",
-                    self.active_processes.join(", ")
+        }
+        self.config_errors.extend(bad_colors);
+        self.no_color = config_bool(configuration, "no_color", &mut self.config_errors)
+            .unwrap_or(false);
+        // gce-suspend / gce-stop read better next to ec2-* and azure-* in a chain
+        self.suspend_chain = config_list(configuration, "suspend_action")
+            .into_iter()
+            .map(|action| match action.as_str() {
+                "gce-suspend" => "suspend".to_string(),
+                "gce-stop" => "stop".to_string(),
+                _ => action,
+            })
+            .collect();
+        if self.suspend_chain.is_empty() {
+            self.suspend_chain = vec![DEFAULT_SUSPEND_ACTION.to_string()];
+        }
+        self.suspend_chain_index = 0;
+        self.suspend_action = self.suspend_chain[0].clone();
+        self.suspend_command = configuration
+            .get("suspend_command")
+            .map(|s| s.trim().to_string())
+            .unwrap_or_default();
+        // A custom suspend_command gets the action as $1, so any name may mean something to it
+        self.bad_suspend_action = self
+            .suspend_chain
+            .iter()
+            .find(|action| {
+                !SUSPEND_ACTIONS.contains(&action.as_str()) && systemd_target(action).is_none()
+            })
+            .filter(|_| self.suspend_command.is_empty())
+            .cloned();
+        self.suspend_disabled_reason = self
+            .bad_suspend_action
+            .as_ref()
+            .map(|action| format!("unknown suspend_action {:?}", action));
+        if let Some(action) = &self.bad_suspend_action {
+            self.config_errors.push(format!(
+                "suspend_action: unknown {:?}, not suspending; expected systemd:<target> or one of {}",
+                action,
+                SUSPEND_ACTIONS.join(", ")
+            ));
+        }
+        self.lock_command = configuration
+            .get("lock_command")
+            .map(|s| s.trim().to_string())
+            .unwrap_or_default();
+        self.fallback_action = match configuration.get("fallback_action").map(|s| s.trim()) {
+            None | Some("") => DEFAULT_FALLBACK_ACTION.to_string(),
+            Some(action @ ("log" | "detach")) => action.to_string(),
+            Some(action) if action.starts_with("command:") => action.to_string(),
+            Some(action) => {
+                self.config_errors.push(format!(
+                    "fallback_action: unknown {:?}, using {:?}; expected log, detach or command:<command>",
+                    action, DEFAULT_FALLBACK_ACTION
                 ));
+                DEFAULT_FALLBACK_ACTION.to_string()
+            }
+        };
+        self.suspend_run_as = configuration
+            .get("suspend_run_as")
+            .map(|s| s.trim().to_string())
+            .unwrap_or_default();
+        let (suspend_env, bad_env): (Vec<_>, Vec<_>) = config_list(configuration, "suspend_env")
+            .into_iter()
+            .partition(|pair| pair.split_once('=').is_some_and(|(key, _)| !key.is_empty()));
+        self.suspend_env = suspend_env;
+        for pair in bad_env {
+            self.config_errors.push(format!(
+                "suspend_env: ignoring {:?}; expected KEY=VALUE",
+                pair
+            ));
+        }
+        let cli_path = |key: &str, default: &str| {
+            configuration
+                .get(key)
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .unwrap_or_else(|| default.to_string())
+        };
+        self.gcloud_path = cli_path("gcloud_path", "gcloud");
+        self.aws_path = cli_path("aws_path", "aws");
+        self.az_path = cli_path("az_path", "az");
+        self.dry_run = config_bool(configuration, "dry_run", &mut self.config_errors)
+            .unwrap_or(false);
+        self.warn_first_cycle = config_bool(configuration, "warn_first_cycle", &mut self.config_errors)
+            .unwrap_or(false);
+        self.snooze_secs = config_secs(configuration, "snooze_secs", &mut self.config_errors)
+            .unwrap_or(DEFAULT_SNOOZE_SECS);
+        self.extend_secs = config_secs(configuration, "extend_secs", &mut self.config_errors)
+            .unwrap_or(DEFAULT_EXTEND_SECS);
+        self.notify_command = configuration
+            .get("notify_command")
+            .map(|s| s.trim().to_string())
+            .unwrap_or_default();
+        self.pre_suspend_command = configuration
+            .get("pre_suspend_command")
+            .map(|s| s.trim().to_string())
+            .unwrap_or_default();
+        self.post_resume_command = configuration
+            .get("post_resume_command")
+            .map(|s| s.trim().to_string())
+            .unwrap_or_default();
+        self.hourly_cost = config_num(configuration, "hourly_cost", &mut self.config_errors)
+            .unwrap_or(0.0);
+        self.selfcheck_on_load = config_bool(configuration, "selfcheck_on_load", &mut self.config_errors)
+            .unwrap_or(false);
+        self.dump_layout_before_suspend = config_bool(configuration, "dump_layout_before_suspend", &mut self.config_errors)
+            .unwrap_or(false);
+        self.layout_dump_path = configuration
+            .get("layout_dump_path")
+            .map(|s| s.trim().to_string())
+            .unwrap_or_default();
+        self.post_suspend_webhook = configuration
+            .get("post_suspend_webhook")
+            .map(|s| s.trim().to_string())
+            .unwrap_or_default();
+        self.webhook_on_success = match configuration
+            .get("post_suspend_webhook_on")
+            .map(|s| s.trim())
+        {
+            None | Some("dispatch") => false,
+            Some("success") => true,
+            Some(other) => {
+                self.config_errors.push(format!(
+                    "post_suspend_webhook_on: unknown {:?}, using dispatch",
+                    other
+                ));
+                false
+            }
+        };
+        self.max_session_secs = config_secs(configuration, "max_session_secs", &mut self.config_errors)
+            .unwrap_or(0.0);
+        self.max_session_extension_secs = config_secs(configuration, "max_session_extension_secs", &mut self.config_errors)
+            .unwrap_or(0.0);
+        self.suspend_max_retries = config_num(configuration, "suspend_max_retries", &mut self.config_errors)
+            .unwrap_or(DEFAULT_SUSPEND_MAX_RETRIES);
+        self.retry_backoff_secs = config_secs(configuration, "retry_backoff_secs", &mut self.config_errors)
+            .unwrap_or(DEFAULT_RETRY_BACKOFF_SECS);
+        self.suspend_timeout_secs = config_secs(configuration, "suspend_timeout_secs", &mut self.config_errors)
+            .unwrap_or(DEFAULT_SUSPEND_TIMEOUT_SECS);
+        self.claude_code_idle_detection = config_bool(configuration, "claude_code_idle_detection", &mut self.config_errors)
+            .unwrap_or(true);
+        self.claude_working_cpu_threshold =
+            config_num(configuration, "claude_working_cpu_threshold", &mut self.config_errors)
+                .unwrap_or(0.0);
+        self.agent_process_patterns = configuration
+            .get("agent_process_patterns")
+            .map(|s| {
+                s.split(',')
+                    .map(|p| p.trim().to_string())
+                    .filter(|p| !p.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+        self.ignore_processes = configuration
+            .get("ignore_processes")
+            .map(|s| {
+                s.split(',')
+                    .map(|p| p.trim().to_string())
+                    .filter(|p| !p.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+        // Entries prefixed with `re:` are regexes rather than literal process names
+        let (patterns, literals): (Vec<String>, Vec<String>) =
+            std::mem::take(&mut self.ignore_processes)
+                .into_iter()
+                .partition(|p| p.starts_with("re:"));
+        self.ignore_processes = literals;
+        for pattern in patterns {
+            let pattern = &pattern["re:".len()..];
+            match Regex::new(pattern) {
+                Ok(re) => self.ignore_patterns.push(re),
+                Err(e) => self.config_errors.push(format!(
+                    "ignore_processes: ignoring invalid regex {:?}: {}",
+                    pattern, e
+                )),
+            }
+        }
+        self.include_processes = config_list(configuration, "include_processes");
+        if !self.include_processes.is_empty()
+            && (!self.ignore_processes.is_empty() || !self.ignore_patterns.is_empty())
+        {
+            self.log(
+                "warning: both include_processes and ignore_processes are set; include_processes wins and ignore_processes has no effect"
+                    .to_string(),
+            );
+        }
+        self.detect_ssh_sessions = config_bool(configuration, "detect_ssh_sessions", &mut self.config_errors)
+            .unwrap_or(true);
+        self.mosh_idle_after_secs = config_secs(configuration, "mosh_idle_after_secs", &mut self.config_errors)
+            .unwrap_or(0.0);
+        self.recurse_multiplexers = config_bool(configuration, "recurse_multiplexers", &mut self.config_errors)
+            .unwrap_or(true);
+        self.cpu_active_threshold_pct = config_num(configuration, "cpu_active_threshold_pct", &mut self.config_errors)
+            .unwrap_or(0.0);
+        self.detect_gpu = config_bool(configuration, "detect_gpu", &mut self.config_errors)
+            .unwrap_or(false);
+        self.gpu_active_threshold_pct = config_num(configuration, "gpu_active_threshold_pct", &mut self.config_errors)
+            .unwrap_or(DEFAULT_GPU_ACTIVE_THRESHOLD_PCT);
+        self.respect_system_idle = config_bool(configuration, "respect_system_idle", &mut self.config_errors)
+            .unwrap_or(false);
+        self.detect_network_active = config_bool(configuration, "detect_network_active", &mut self.config_errors)
+            .unwrap_or(false);
+        self.detect_containers = config_bool(configuration, "detect_containers", &mut self.config_errors)
+            .unwrap_or(false);
+        self.container_cpu_threshold_pct = config_num(configuration, "container_cpu_threshold_pct", &mut self.config_errors)
+            .unwrap_or(DEFAULT_CONTAINER_CPU_THRESHOLD_PCT);
+        self.network_active_kbps = config_num(configuration, "network_active_kbps", &mut self.config_errors)
+            .unwrap_or(DEFAULT_NETWORK_ACTIVE_KBPS);
+        self.min_uptime_secs = config_secs(configuration, "min_uptime_secs", &mut self.config_errors)
+            .unwrap_or(0.0);
+        self.keypress_resets_idle = config_bool(configuration, "keypress_resets_idle", &mut self.config_errors)
+            .unwrap_or(true);
+        self.input_debounce_secs = config_secs(configuration, "input_debounce_secs", &mut self.config_errors)
+            .unwrap_or(0.0);
+        self.protect_editors = config_bool(configuration, "protect_editors", &mut self.config_errors)
+            .unwrap_or(false);
+        self.idle_ratio_threshold = match config_num(configuration, "idle_ratio_threshold", &mut self.config_errors) {
+            Some(ratio) if ratio > 0.0 && ratio <= 1.0 => ratio,
+            Some(ratio) => {
+                self.config_errors.push(format!(
+                    "idle_ratio_threshold: {} is outside (0, 1]; using 1",
+                    ratio
+                ));
+                1.0
+            }
+            None => 1.0,
+        };
+        self.treat_stdin_wait_as_idle = config_bool(configuration, "treat_stdin_wait_as_idle", &mut self.config_errors)
+            .unwrap_or(false);
+        self.ignore_tabs = config_list(configuration, "ignore_tabs");
+        self.count_tabs = config_list(configuration, "count_tabs");
+        self.require_no_clients = config_bool(configuration, "require_no_clients", &mut self.config_errors)
+            .unwrap_or(false);
+        self.battery_only = config_bool(configuration, "battery_only", &mut self.config_errors)
+            .unwrap_or(false);
+        self.battery_timeout_secs = config_secs(configuration, "battery_timeout_secs", &mut self.config_errors)
+            .unwrap_or(0.0);
+        self.detached_idle_timeout_secs =
+            config_secs(configuration, "detached_idle_timeout_secs", &mut self.config_errors)
+                .unwrap_or(0.0);
+        self.event_driven = config_bool(configuration, "event_driven", &mut self.config_errors)
+            .unwrap_or(false);
+        self.safety_poll_secs = config_secs(configuration, "safety_poll_secs", &mut self.config_errors)
+            .unwrap_or(DEFAULT_SAFETY_POLL_SECS);
+        self.consecutive_idle_polls =
+            config_num(configuration, "consecutive_idle_polls", &mut self.config_errors)
+                .unwrap_or(1)
+                .max(1);
+        self.child_pid_refresh_polls =
+            config_num(configuration, "child_pid_refresh_polls", &mut self.config_errors)
+                .unwrap_or(DEFAULT_CHILD_PID_REFRESH_POLLS);
+        self.custom_idle_check = configuration
+            .get("idle_check_command")
+            .map(|s| s.trim().to_string())
+            .unwrap_or_default();
+        self.max_panes_for_detection =
+            config_num(configuration, "max_panes_for_detection", &mut self.config_errors)
+                .unwrap_or(0);
+        let active_start = configuration.get("active_start");
+        let active_end = configuration.get("active_end");
+        if active_start.is_some() || active_end.is_some() {
+            match (
+                active_start.and_then(|s| parse_hhmm(s)),
+                active_end.and_then(|s| parse_hhmm(s)),
+            ) {
+                (Some(start), Some(end)) => self.active_window = Some((start, end)),
+                _ => self.config_errors.push(format!(
+                    "active_start/active_end: {:?} and {:?} must both be HH:MM, ignoring active hours",
+                    active_start, active_end
+                )),
+            }
+        }
+
+        for error in self.config_errors.clone() {
+            self.log(format!("config error: {}", error));
+        }
+        self.log_event("config", serde_json::json!({}), format!(
+            "loaded config: enabled={}, poll_interval={}s, max_poll_interval={}s, poll_backoff_after={}s, poll_jitter={}s, idle_timeout={}s, timeout_schedule={:?}, countdown={}s, countdown_warnings={:?}, bell_at={}s, suspend_banner_hold={}s, require_confirm={}, countdown_mmss={}, suspend_action={}, suspend_command={:?}, suspend_run_as={:?}, suspend_env={:?}, lock_command={:?}, fallback_action={:?}, gcloud_path={:?}, aws_path={:?}, az_path={:?}, dry_run={}, warn_first_cycle={}, snooze={}s, extend={}s, notify_command={:?}, pre_suspend_command={:?}, post_resume_command={:?}, dump_layout={}, layout_dump_path={:?}, selfcheck_on_load={}, hourly_cost={}, post_suspend_webhook={:?}, webhook_on_success={}, max_session={}s, max_session_extension={}s, suspend_max_retries={}, retry_backoff={}s, suspend_timeout={}s, claude_detect={}, claude_cpu_threshold={}%, agent_patterns={:?}, ignore={:?}, ignore_patterns={:?}, include={:?}, protect_editors={}, idle_ratio={}, consecutive_idle_polls={}, stdin_wait_idle={}, ignore_tabs={:?}, count_tabs={:?}, detect_ssh={}, mosh_idle_after={}s, recurse_mux={}, cpu_threshold={}%, detect_gpu={}, gpu_threshold={}%, detect_net={}, net_threshold={}kbps, detect_containers={}, container_threshold={}%, respect_system_idle={}, active_window={:?}, min_uptime={}s, require_no_clients={}, detached_timeout={}s, battery_only={}, battery_timeout={}s, keypress_resets_idle={}, input_debounce={}s, event_driven={}, safety_poll={}s, child_pid_refresh={} polls, max_panes={}, idle_check_command={:?}, log_format={}, zellij_pid={}",
+            self.enabled, self.poll_interval_secs, self.max_poll_interval_secs, self.poll_backoff_after_secs, self.poll_jitter_secs, self.idle_timeout_secs, self.timeout_schedule.iter().map(|r| format!("{}={}", r.label, r.timeout_secs)).collect::<Vec<_>>(), self.countdown_secs, self.countdown_warnings, self.bell_at_secs, self.suspend_banner_hold_secs, self.require_confirm_suspend, self.countdown_mmss, self.suspend_chain.join(","), self.suspend_command, self.suspend_run_as, self.suspend_env, self.lock_command, self.fallback_action, self.gcloud_path, self.aws_path, self.az_path,
+            self.dry_run, self.warn_first_cycle, self.snooze_secs, self.extend_secs, self.notify_command, self.pre_suspend_command, self.post_resume_command, self.dump_layout_before_suspend, self.layout_dump_path, self.selfcheck_on_load, self.hourly_cost, self.post_suspend_webhook, self.webhook_on_success, self.max_session_secs, self.max_session_extension_secs, self.suspend_max_retries, self.retry_backoff_secs, self.suspend_timeout_secs, self.claude_code_idle_detection, self.claude_working_cpu_threshold, self.agent_process_patterns, self.ignore_processes,
+            self.ignore_patterns.iter().map(|re| re.as_str()).collect::<Vec<_>>(), self.include_processes, self.protect_editors, self.idle_ratio_threshold, self.consecutive_idle_polls, self.treat_stdin_wait_as_idle, self.ignore_tabs, self.count_tabs, self.detect_ssh_sessions, self.mosh_idle_after_secs, self.recurse_multiplexers, self.cpu_active_threshold_pct, self.detect_gpu, self.gpu_active_threshold_pct, self.detect_network_active, self.network_active_kbps, self.detect_containers, self.container_cpu_threshold_pct, self.respect_system_idle, self.active_window, self.min_uptime_secs, self.require_no_clients, self.detached_idle_timeout_secs, self.battery_only, self.battery_timeout_secs, self.keypress_resets_idle, self.input_debounce_secs, self.event_driven, self.safety_poll_secs, self.child_pid_refresh_polls, self.max_panes_for_detection, self.custom_idle_check, if self.log_json { "json" } else { "text" }, self.zellij_pid
+        ));
+    }
+
+    /// Machine-readable snapshot of the plugin's state for the zellij-idle-query pipe.
+    fn state_json(&self) -> String {
+        serde_json::json!({
+            "is_idle": self.is_idle,
+            "idle_elapsed_secs": self.idle_elapsed_secs,
+            "countdown_active": self.countdown_active,
+            "countdown_remaining": self.countdown_remaining,
+            "maxtime_countdown": self.maxtime_countdown,
+            "active_pane_count": self.active_pane_count,
+            "shell_count": self.shell_count,
+            "zellij_pane_count": self.zellij_pane_count,
+            "active_processes": self.active_processes,
+            "suspend_triggered": self.suspend_triggered,
+            "awaiting_confirm": self.awaiting_confirm,
+            "idle_timeout_secs": self.idle_timeout(),
+            "timeout_profile": self.timeout_profile,
+            "config_errors": self.config_errors,
+            "child_pid_lookups": self.child_pid_lookups,
+            "child_pid_reuses": self.child_pid_reuses,
+            "input_noise_events": self.input_noise_events,
+            "suspend_disabled_reason": self.suspend_disabled_reason,
+            "savings": self.savings_json(),
+        })
+        .to_string()
+    }
+
+    /// The savings counters for zellij-idle-query.
+    fn savings_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "suspends": self.savings.suspends,
+            "suspends_last_7d": self.savings.recent_suspends(unix_now()),
+            "suspended_hours": self.savings.suspended_secs / 3600.0,
+            "hourly_cost": self.hourly_cost,
+            "estimated_saved": self.savings.estimated_saved(self.hourly_cost),
+        })
+    }
+
+    /// Logs the end of a suspend that lasted `secs`, with the running totals.
+    fn log_savings(&mut self, secs: f64) {
+        let mut msg = format!(
+            "was suspended for {:.1}h; {} suspends ({} in the last week), {:.1}h in total",
+            secs / 3600.0,
+            self.savings.suspends,
+            self.savings.recent_suspends(unix_now()),
+            self.savings.suspended_secs / 3600.0
+        );
+        if self.hourly_cost > 0.0 {
+            msg.push_str(&format!(
+                ", about {:.2} saved",
+                self.savings.estimated_saved(self.hourly_cost)
+            ));
+        }
+        self.log(msg);
+    }
+
+    /// Writes the savings counters to their file, once it's been read. Until then they
+    /// stay in memory, and the read adds them to what the file holds.
+    fn save_savings(&self) {
+        if !self.savings_loaded {
+            return;
+        }
+        let mut context = BTreeMap::new();
+        context.insert("command".to_string(), "savings_write".to_string());
+        run_command(
+            &["bash", "-c", SAVINGS_FILE_SCRIPT, "_", "write", &self.savings.serialize()],
+            context,
+        );
+    }
+
+    /// Sends `body` back to whoever sent a pipe message. Only CLI pipes can receive output.
+    fn reply(&self, source: &PipeSource, body: &str) {
+        if let PipeSource::Cli(pipe_id) = source {
+            cli_pipe_output(pipe_id, &format!("{}\n", body));
+            unblock_cli_pipe_input(pipe_id);
+        }
+    }
+
+    /// The `key=value` lines of a config pipe payload, skipping blank lines and `#`
+    /// comments and logging the rest that don't parse.
+    fn parse_config_lines(&mut self, pipe: &str, text: &str) -> BTreeMap<String, String> {
+        let mut config = BTreeMap::new();
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.split_once('=') {
+                Some((key, value)) => {
+                    config.insert(key.trim().to_string(), value.trim().to_string());
+                }
+                None => self.log(format!("{}: ignoring {:?}", pipe, line)),
+            }
+        }
+        config
+    }
+
+    /// Swaps in the config in `text` for zellij-idle-reload and returns the reply.
+    /// Runtime state like poll_count and the idle timer carries on as it was.
+    fn reload_config(&mut self, text: &str) -> String {
+        let config = self.parse_config_lines("zellij-idle-reload", text);
+        let changed: Vec<String> = config
+            .iter()
+            .filter(|(key, value)| self.config.get(*key) != Some(*value))
+            .map(|(key, _)| key.clone())
+            .chain(self.config.keys().filter(|key| !config.contains_key(*key)).cloned())
+            .collect();
+        self.config = config;
+        self.log(format!("config reloaded via zellij-idle-reload: {}", changed.join(", ")));
+        self.reconfigure(&changed);
+        format!("reloaded: {}", changed.join(", "))
+    }
+
+    /// Re-parses self.config after a pipe changed the keys in `changed`, and redoes what
+    /// load does with the settings that need it.
+    fn reconfigure(&mut self, changed: &[String]) {
+        let config = self.config.clone();
+        self.configure(&config);
+        if changed.iter().any(|key| {
+            matches!(
+                key.as_str(),
+                "suspend_action" | "suspend_command" | "gcloud_path" | "aws_path" | "az_path"
+            )
+        }) {
+            self.cli_missing = false;
+            self.check_suspend_cli();
+        }
+        if changed.iter().any(|key| key == "min_uptime_secs") {
+            self.fetch_uptime();
+        }
+    }
+
+    /// The part of the state file that changes on a state transition rather than with
+    /// the clock, for update to tell when it needs writing between ticks.
+    fn persisted_phase(&self) -> (bool, bool, bool, bool, bool) {
+        (
+            self.is_idle,
+            self.countdown_active,
+            self.maxtime_countdown,
+            self.session_extended,
+            self.first_cycle_warned,
+        )
+    }
+
+    /// Writes the counters that should survive a plugin reload to the state file.
+    fn save_state(&mut self) {
+        let persisted = PersistedState {
+            saved_at_unix: unix_now(),
+            poll_count: self.poll_count,
+            clock_secs: self.clock_secs,
+            last_activity_secs: self.last_activity_secs,
+            is_idle: self.is_idle,
+            idle_elapsed_secs: self.idle_elapsed_secs,
+            countdown_active: self.countdown_active,
+            countdown_remaining: self.countdown_remaining,
+            session_start_unix: self.session_start_unix,
+            maxtime_countdown: self.maxtime_countdown,
+            session_extended: self.session_extended,
+            first_cycle_warned: self.first_cycle_warned,
+        };
+        let path = persist::state_file_path(self.zellij_pid);
+        if let Err(e) = std::fs::write(&path, persisted.serialize()) {
+            if !self.state_file_error_logged {
+                self.state_file_error_logged = true;
+                self.log(format!("failed to write state file {}: {}", path, e));
+            }
+        }
+    }
+
+    /// Restores counters saved by a previous instance of the plugin, but only if they
+    /// were written within the longest poll interval; anything older is stale.
+    fn restore_state(&mut self) {
+        let path = persist::state_file_path(self.zellij_pid);
+        let Some(persisted) = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|contents| PersistedState::parse(&contents))
+        else {
+            return;
+        };
+        // The file belongs to this zellij session, so however old, it was warned already
+        self.first_cycle_warned = persisted.first_cycle_warned;
+        let now = unix_now();
+        if !persisted.is_fresh(now, self.poll_interval_secs.max(self.max_poll_interval_secs)) {
+            let age = now - persisted.saved_at_unix;
+            self.log(format!("ignoring stale state file {} ({:.1}s old)", path, age));
+            return;
+        }
+        self.poll_count = persisted.poll_count;
+        self.clock_secs = persisted.clock_secs;
+        self.last_activity_secs = persisted.last_activity_secs;
+        self.is_idle = persisted.is_idle;
+        self.idle_elapsed_secs = persisted.idle_elapsed_secs;
+        self.countdown_active = persisted.countdown_active;
+        self.countdown_remaining = persisted.countdown_remaining;
+        if persisted.session_start_unix > 0.0 {
+            self.session_start_unix = persisted.session_start_unix;
+        }
+        self.maxtime_countdown = persisted.maxtime_countdown;
+        self.session_extended = persisted.session_extended;
+        self.log(format!(
+            "restored state from {}: poll #{}, idle={}, idle_elapsed={}s, countdown={}",
+            path,
+            self.poll_count,
+            self.is_idle,
+            self.idle_elapsed_secs as u64,
+            self.countdown_active
+        ));
+    }
+
+    /// Whether this is warn_first_cycle's one countdown that only warns.
+    fn warning_cycle(&self) -> bool {
+        self.warn_first_cycle && !self.first_cycle_warned
+    }
+
+    /// Why entering the countdown is currently being held back, if it is.
+    fn suspend_suppressed_reason(&self) -> Option<&'static str> {
+        if let Some(boot) = self.boot_unix {
+            if unix_now() - boot < self.min_uptime_secs {
+                return Some("warmup");
+            }
+        }
+        if let (Some((start, end)), Some(now)) = (self.active_window, self.local_time_minutes) {
+            if in_window(now, start, end) {
+                return Some("quiet-hrs");
+            }
+        }
+        if self.client_attached() {
+            return Some("client attached");
+        }
+        if self.battery_only && !self.on_battery() {
+            return Some("on AC");
+        }
+        None
+    }
+
+    /// Whether require_no_clients holds suspend off. Until zellij reports the session's
+    /// clients, assume someone is attached.
+    fn client_attached(&self) -> bool {
+        self.require_no_clients && self.connected_clients.unwrap_or(1) > 0
+    }
+
+    /// Whether the last idle check found the box running on battery. Unknown counts as
+    /// AC, since boxes without a battery are plugged in.
+    fn on_battery(&self) -> bool {
+        self.power_source.as_deref() == Some("battery")
+    }
+
+    fn set_suppressed_reason(&mut self, reason: Option<&'static str>) {
+        if reason == self.suppressed_reason {
+            return;
+        }
+        match reason {
+            Some(r) => self.log(format!("countdown suppressed ({})", r)),
+            None => self.log("countdown no longer suppressed".to_string()),
+        }
+        self.suppressed_reason = reason;
+    }
+
+    fn refresh_local_time(&self) {
+        let mut context = BTreeMap::new();
+        context.insert("command".to_string(), "clock".to_string());
+        run_command(&["date", "+%u %H:%M"], context);
+    }
+
+    /// The timeout_schedule rule for the current local day and time, if any.
+    fn timeout_rule(&self) -> Option<&TimeoutRule> {
+        self.timeout_schedule
+            .iter()
+            .find(|rule| rule.matches(self.local_weekday, self.local_time_minutes))
+    }
+
+    /// The idle timeout in effect right now: the matching timeout_schedule rule's, or
+    /// idle_timeout_secs when none matches.
+    fn idle_timeout(&self) -> f64 {
+        if self.detached_timeout_applies() {
+            return self.detached_idle_timeout_secs;
+        }
+        if self.battery_only && self.battery_timeout_secs > 0.0 && self.on_battery() {
+            return self.battery_timeout_secs;
+        }
+        self.timeout_rule()
+            .map_or(self.idle_timeout_secs, |rule| rule.timeout_secs)
+    }
+
+    /// Whether detached_idle_timeout_secs is set and zellij reports no client attached.
+    fn detached_timeout_applies(&self) -> bool {
+        self.detached_idle_timeout_secs > 0.0 && self.connected_clients == Some(0)
+    }
+
+    /// Tracks which timeout_schedule rule is in effect, or "detached" for
+    /// detached_idle_timeout_secs, and logs when that changes. The new timeout applies at
+    /// once, including to an idle stretch already under way.
+    fn update_timeout_profile(&mut self) {
+        let profile = if self.detached_timeout_applies() {
+            Some("detached".to_string())
+        } else {
+            self.timeout_rule().map(|rule| rule.label.clone())
+        };
+        if profile != self.timeout_profile {
+            self.log(format!(
+                "timeout profile: {} (idle timeout {}s)",
+                profile.as_deref().unwrap_or("default"),
+                self.idle_timeout()
+            ));
+            self.timeout_profile = profile;
+        }
+    }
+
+    /// Recomputes idle_elapsed_secs from the poll clock, so it keeps counting through
+    /// a countdown and can't drift from it.
+    fn update_idle_elapsed(&mut self) {
+        self.idle_elapsed_secs = (self.clock_secs - self.last_activity_secs).max(0.0);
+    }
+
+    /// The interval until the next tick: poll_interval_secs, except that once idle for
+    /// poll_backoff_after_secs it doubles each tick up to max_poll_interval_secs. It never
+    /// sleeps past the idle timeout, so the countdown still starts on time.
+    fn next_poll_interval(&self) -> f64 {
+        let fast = self.poll_interval_secs;
+        let backing_off = self.is_idle
+            && !self.countdown_active
+            && !self.suspend_triggered
+            && self.suspend_retry_at_unix.is_none()
+            && self.idle_elapsed_secs >= self.poll_backoff_after_secs;
+        if !backing_off || self.max_poll_interval_secs <= fast {
+            return fast;
+        }
+        let until_timeout = self.idle_timeout() - self.idle_elapsed_secs;
+        (self.tick_interval_secs * 2.0)
+            .min(self.max_poll_interval_secs)
+            .min(until_timeout)
+            .max(fast)
+    }
+
+    /// After backing off while idle, ticks at poll_interval_secs again from now rather
+    /// than after the long interval already scheduled. Not for use inside a Timer tick,
+    /// which schedules its successor itself.
+    fn snap_back_poll_interval(&mut self) {
+        if self.tick_interval_secs > self.poll_interval_secs {
+            self.log("activity, back to the fast poll interval".to_string());
+            self.last_tick_unix = unix_now();
+            self.schedule_tick(self.poll_interval_secs);
+        }
+    }
+
+    fn schedule_tick(&mut self, secs: f64) {
+        self.tick_interval_secs = secs;
+        // Never so early that it ticks back to back
+        self.tick_delay_secs = (secs + self.next_jitter()).max(secs / 2.0);
+        self.next_tick_unix = unix_now() + self.tick_delay_secs;
+        set_timeout(self.tick_delay_secs);
+    }
+
+    /// How long the timer that just fired waited.
+    fn tick_elapsed_secs(&self) -> f64 {
+        if self.poll_jitter_secs > 0.0 {
+            self.tick_delay_secs
+        } else {
+            self.tick_interval_secs
+        }
+    }
+
+    /// A random offset within ±poll_jitter_secs, from a xorshift generator seeded with
+    /// zellij_pid: the same sequence on each box, but a different one on the next.
+    fn next_jitter(&mut self) -> f64 {
+        if self.poll_jitter_secs <= 0.0 {
+            return 0.0;
+        }
+        if self.jitter_rng == 0 {
+            self.jitter_rng = (u64::from(self.zellij_pid) + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        }
+        let mut x = self.jitter_rng;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.jitter_rng = x;
+        let unit = (x >> 11) as f64 / (1u64 << 53) as f64;
+        (unit * 2.0 - 1.0) * self.poll_jitter_secs
+    }
+
+    /// Starts the countdown and returns its length.
+    fn start_countdown(&mut self) -> f64 {
+        // A warning threshold above countdown_secs lengthens the countdown
+        // so that every stage gets its turn.
+        let length = self
+            .countdown_warnings
+            .first()
+            .map_or(self.countdown_secs, |t| t.max(self.countdown_secs));
+        self.countdown_active = true;
+        self.countdown_remaining = length;
+        self.countdown_stage = self.countdown_stage_for(length);
+        self.bell_rung = false;
+        length
+    }
+
+    /// Session length after which the max_session_secs countdown starts.
+    fn session_limit_secs(&self) -> f64 {
+        if self.session_extended {
+            self.max_session_secs + self.max_session_extension_secs
+        } else {
+            self.max_session_secs
+        }
+    }
+
+    /// How many countdown_warnings thresholds `remaining` has reached.
+    fn countdown_stage_for(&self, remaining: f64) -> usize {
+        self.countdown_warnings.iter().filter(|t| remaining <= **t).count()
+    }
+
+    /// SGR code for the countdown banner, escalating from yellow to red as warning
+    /// thresholds are crossed.
+    fn countdown_color(&self) -> &str {
+        let stages = self.countdown_warnings.len();
+        if self.countdown_stage == 0 {
+            &self.color_countdown
+        } else if self.countdown_stage < stages {
+            &self.color_warning
+        } else {
+            &self.color_suspend
+        }
+    }
+
+    /// Whether zellij's descendants used more CPU than cpu_active_threshold_pct since the
+    /// previous poll. The first sample only establishes a baseline.
+    fn cpu_busy(&mut self, dump: &str) -> bool {
+        if self.cpu_active_threshold_pct <= 0.0 {
+            return false;
+        }
+        let Some(ticks) = classify::dump_value(dump, "cputicks").and_then(|v| v.parse::<u64>().ok())
+        else {
+            return false;
+        };
+        let clk_tck = classify::dump_value(dump, "clktck")
+            .and_then(|v| v.parse::<f64>().ok())
+            .filter(|t| *t > 0.0)
+            .unwrap_or(100.0);
+        let now = unix_now();
+        let previous = self.last_cpu_sample.replace((now, ticks));
+        let Some((prev_time, prev_ticks)) = previous else {
+            return false;
+        };
+        let elapsed = now - prev_time;
+        if elapsed <= 0.0 {
+            return false;
+        }
+        // Exited processes take their ticks with them, so the sum can go down.
+        let used_secs = ticks.saturating_sub(prev_ticks) as f64 / clk_tck;
+        let pct = used_secs / elapsed * 100.0;
+        if pct > self.cpu_active_threshold_pct {
+            self.log(format!(
+                "cpu-busy: descendants used {:.0}% cpu (threshold {}%)",
+                pct, self.cpu_active_threshold_pct
+            ));
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Works out each pane's foreground tree CPU use from the ticks in this dump and the
+    /// last, for claude_working_cpu_threshold. A pane whose foreground changed in between
+    /// can show fewer ticks; it gets no figure this time.
+    fn update_agent_cpu(&mut self, dump: &str) {
+        self.agent_cpu_pct.clear();
+        if self.claude_working_cpu_threshold <= 0.0 {
+            return;
+        }
+        let clk_tck = classify::dump_value(dump, "clktck")
+            .and_then(|v| v.parse::<f64>().ok())
+            .filter(|t| *t > 0.0)
+            .unwrap_or(100.0);
+        let now = unix_now();
+        let mut ticks = BTreeMap::new();
+        for line in dump.lines() {
+            let Some((pid, value)) = line.strip_prefix("fgtree:").and_then(|rest| rest.split_once(':'))
+            else {
+                continue;
+            };
+            let Some(tree_ticks) = value.split_whitespace().next().and_then(|t| t.parse::<u64>().ok())
+            else {
+                continue;
+            };
+            if let Some(&(then, prev_ticks)) = self.agent_ticks.get(pid) {
+                if now > then && tree_ticks >= prev_ticks {
+                    let used_secs = (tree_ticks - prev_ticks) as f64 / clk_tck;
+                    self.agent_cpu_pct.insert(pid.to_string(), used_secs / (now - then) * 100.0);
+                }
+            }
+            ticks.insert(pid.to_string(), (now, tree_ticks));
+        }
+        self.agent_ticks = ticks;
+    }
+
+    /// Whether any GPU's utilization is above gpu_active_threshold_pct. A box without
+    /// nvidia-smi never counts as GPU-busy.
+    fn gpu_busy(&mut self, dump: &str) -> bool {
+        let Some(util) = classify::dump_value(dump, "gpuutil") else {
+            return false;
+        };
+        if util == "unavailable" {
+            if !self.gpu_missing_logged {
+                self.gpu_missing_logged = true;
+                self.log("detect_gpu: nvidia-smi not available, ignoring GPU usage".to_string());
+            }
+            return false;
+        }
+        let busiest = util
+            .split(',')
+            .filter_map(|v| v.trim().parse::<f64>().ok())
+            .fold(0.0, f64::max);
+        if busiest > self.gpu_active_threshold_pct {
+            self.log(format!(
+                "gpu-busy: a GPU is at {:.0}% utilization (threshold {}%)",
+                busiest, self.gpu_active_threshold_pct
+            ));
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Whether the machine itself got keyboard or mouse input since the previous poll, for
+    /// respect_system_idle. With no way to tell, it doesn't count.
+    fn desktop_active(&mut self, dump: &str) -> bool {
+        let Some(idle) = classify::dump_value(dump, "sysidle") else {
+            return false;
+        };
+        let Ok(idle) = idle.parse::<f64>() else {
+            if !self.system_idle_missing_logged {
+                self.system_idle_missing_logged = true;
+                self.log(
+                    "respect_system_idle: neither xprintidle nor loginctl reports an idle time, ignoring it"
+                        .to_string(),
+                );
+            }
+            return false;
+        };
+        if idle < self.tick_interval_secs {
+            self.log(format!("desktop-active: last input to the machine {}s ago", idle as u64));
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Whether the dump stopped short at max_panes_for_detection. Logged when that
+    /// starts and when it ends.
+    fn too_many_panes(&mut self, dump: &str) -> bool {
+        let panes = classify::dump_value(dump, "toomanypanes");
+        match (panes, self.too_many_panes) {
+            (Some(panes), false) => self.log(format!(
+                "warning: {} pane shells, over max_panes_for_detection {}; not scanning them and treating the box as active until there are fewer",
+                panes, self.max_panes_for_detection
+            )),
+            (None, true) => self.log(format!(
+                "back under max_panes_for_detection {}, scanning panes again",
+                self.max_panes_for_detection
+            )),
+            _ => {}
+        }
+        self.too_many_panes = panes.is_some();
+        self.too_many_panes
+    }
+
+    /// Whether a running container is above container_cpu_threshold_pct. Also keeps the
+    /// container engines the dump looked up, so later dumps don't search PATH again.
+    ///
+    /// An engine whose stats timed out is taken as busy, since a daemon too loaded to
+    /// answer may well be running a build. Any other failure (e.g. the daemon isn't
+    /// running, so no container is either) is logged and otherwise ignored.
+    fn container_busy(&mut self, dump: &str) -> bool {
+        if !self.detect_containers {
+            return false;
+        }
+        if let Some(engines) = classify::dump_value(dump, "containerengines") {
+            if engines == "none" {
+                self.log("detect_containers: neither docker nor podman found".to_string());
+            } else {
+                self.log(format!("detect_containers: using {}", engines));
+            }
+            self.container_engines = Some(engines.to_string());
+        }
+        let error = classify::dump_value(dump, "containererror").map(str::to_string);
+        let timed_out = error.as_deref().is_some_and(|e| e.ends_with(" 124"));
+        if error != self.container_error {
+            match &error {
+                Some(error) if timed_out => self.log(format!(
+                    "warning: detect_containers: {} stats timed out, treating the box as busy until it answers",
+                    error.trim_end_matches(" 124")
+                )),
+                Some(error) => {
+                    let (engine, status) = error.split_once(' ').unwrap_or((error, "?"));
+                    self.log(format!(
+                        "warning: detect_containers: {} stats failed (exit {}), ignoring containers until it works",
+                        engine, status
+                    ));
+                }
+                None => self.log("detect_containers: container stats work again".to_string()),
+            }
+            self.container_error = error;
+        }
+        if timed_out {
+            return true;
+        }
+        let busy: Vec<String> = dump
+            .lines()
+            .filter_map(|line| line.strip_prefix("containercpu:-:"))
+            .filter_map(|rest| {
+                let (name, cpu) = rest.trim().rsplit_once(' ')?;
+                let cpu: f64 = cpu.trim_end_matches('%').parse().ok()?;
+                (cpu > self.container_cpu_threshold_pct).then(|| format!("{} {:.0}%", name, cpu))
+            })
+            .collect();
+        if busy.is_empty() {
+            return false;
+        }
+        self.log(format!(
+            "container-busy: {} (threshold {}%)",
+            busy.join(", "),
+            self.container_cpu_threshold_pct
+        ));
+        true
+    }
+
+    /// Whether network throughput since the previous poll was above network_active_kbps.
+    /// The first sample only establishes a baseline.
+    fn net_busy(&mut self, dump: &str) -> bool {
+        let Some(bytes) = classify::dump_value(dump, "netbytes").and_then(|v| v.parse::<u64>().ok())
+        else {
+            return false;
+        };
+        let now = unix_now();
+        let Some((prev_time, prev_bytes)) = self.last_net_sample.replace((now, bytes)) else {
+            return false;
+        };
+        let elapsed = now - prev_time;
+        if elapsed <= 0.0 {
+            return false;
+        }
+        // Counters reset when an interface goes away, so the sum can go down.
+        let kbps = bytes.saturating_sub(prev_bytes) as f64 * 8.0 / 1000.0 / elapsed;
+        if kbps > self.network_active_kbps {
+            self.log(format!(
+                "net-busy: {:.0}kbps of network traffic (threshold {}kbps)",
+                kbps, self.network_active_kbps
+            ));
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Runs the user's notify_command (if any) with the seconds left before suspend as $1.
+    fn notify(&self, remaining_secs: f64) {
+        if self.notify_command.is_empty() {
+            return;
+        }
+        let remaining = (remaining_secs.max(0.0) as u64).to_string();
+        let mut context = BTreeMap::new();
+        context.insert("command".to_string(), "notify".to_string());
+        run_command(
+            &["bash", "-c", &self.notify_command, "_", &remaining],
+            context,
+        );
+    }
+
+    fn is_snoozed(&self) -> bool {
+        self.clock_secs < self.snooze_until_secs
+    }
+
+    /// Cancels any countdown and holds off idle detection for `secs`.
+    fn snooze(&mut self, secs: f64) {
+        self.snooze_until_secs = self.clock_secs + secs.max(0.0);
+        self.log(format!("snoozed for {}s", secs as u64));
+        self.reset_activity();
+    }
+
+    /// Buys `secs` more time: on a running countdown, or else on the idle timeout, without
+    /// counting as activity. Returns a reply for the pipe.
+    fn extend(&mut self, secs: f64) -> String {
+        if self.suspend_triggered {
+            self.log("zellij-idle-extend: too late, suspend already triggered".to_string());
+            return "too late: suspend already triggered".to_string();
+        }
+        if self.countdown_active {
+            self.countdown_remaining += secs;
+            self.countdown_stage = self.countdown_stage_for(self.countdown_remaining);
+            if self.countdown_remaining > self.bell_at_secs {
+                self.bell_rung = false;
+            }
+        } else {
+            // Never further than `secs` from now, however often it's sent
+            self.last_activity_secs = (self.last_activity_secs + secs).min(self.clock_secs + secs);
+            self.update_idle_elapsed();
+        }
+        self.extend_flash = Some((secs, unix_now() + EXTEND_FLASH_SECS));
+        let msg = if self.countdown_active {
+            format!("countdown extended by {}s to {}s", secs as u64, self.countdown_remaining as u64)
+        } else {
+            format!("idle timeout extended by {}s", secs as u64)
+        };
+        self.log(format!("zellij-idle-extend: {}", msg));
+        msg
+    }
+
+    /// Drops the suspend latch after failed suspend attempts and starts the idle timeout
+    /// over, so the countdown and suspend run again once it's used up. Retries in
+    /// backoff keep the latch, so only this re-arms it.
+    fn restart_idle_cycle(&mut self) {
+        self.suspend_triggered = false;
+        self.suspend_command_sent = false;
+        self.suspend_attempts = 0;
+        self.reset_suspend_chain();
+        self.maxtime_countdown = false;
+        self.countdown_active = false;
+        self.countdown_remaining = 0.0;
+        self.last_activity_secs = self.clock_secs;
+        self.idle_elapsed_secs = 0.0;
+    }
+
+    /// Clears idle tracking, any countdown, and the suspend latch, as if the user just
+    /// became active.
+    fn reset_activity(&mut self) {
+        self.last_activity_secs = self.clock_secs;
+        self.snap_back_poll_interval();
+        self.idle_elapsed_secs = 0.0;
+        self.is_idle = false;
+        // Like a busy poll, activity starts consecutive_idle_polls over
+        self.idle_poll_streak = 0;
+        self.suppressed_reason = None;
+        if self.maxtime_countdown {
+            // Only a resume ends the session cap's countdown or suspend
+            return;
+        }
+        self.cancel_suspend();
+    }
+
+    /// Cancels any countdown, a pending retry and the suspend latch. A pre_suspend_command
+    /// still running sees the cleared latch when it exits and doesn't suspend.
+    fn cancel_suspend(&mut self) {
+        self.countdown_active = false;
+        self.countdown_remaining = 0.0;
+        self.suspend_triggered = false;
+        self.suspend_command_sent = false;
+        self.suspend_attempts = 0;
+        self.suspend_retry_at_unix = None;
+        self.suspend_hold_until_secs = None;
+        self.awaiting_confirm = false;
+        self.confirm_input_unix = None;
+        self.reset_suspend_chain();
+    }
+
+    /// Goes back to the first action of suspend_chain.
+    fn reset_suspend_chain(&mut self) {
+        self.suspend_chain_index = 0;
+        if let Some(first) = self.suspend_chain.first() {
+            self.suspend_action = first.clone();
+        }
+    }
+
+    /// Terminal pane ids that ignore_tabs / count_tabs leave out of idle detection. Panes
+    /// whose tab isn't known yet are counted.
+    fn excluded_panes(&self) -> Vec<u32> {
+        if self.ignore_tabs.is_empty() && self.count_tabs.is_empty() {
+            return Vec::new();
+        }
+        self.pane_tabs
+            .iter()
+            .filter(|(_, tab)| {
+                self.tab_names.get(tab).is_some_and(|name| {
+                    self.ignore_tabs.contains(name)
+                        || (!self.count_tabs.is_empty() && !self.count_tabs.contains(name))
+                })
+            })
+            .map(|(pane, _)| *pane)
+            .collect()
+    }
+
+    /// Records the power source from an idle check dump and logs when it changes.
+    fn update_power_source(&mut self, dump: &str) {
+        let power = classify::dump_value(dump, "power").map(str::to_string);
+        if power.is_some() && power != self.power_source {
+            self.log(format!("power source: {}", power.as_deref().unwrap_or("")));
+            self.power_source = power;
+        }
+    }
+
+    /// Logs which pane and tab each shell in an idle check dump belongs to, whenever that
+    /// changes, e.g. `4123 -> pane 2 (tab "monitor", ignored)`.
+    fn log_pane_mapping(&mut self, dump: &str) {
+        let excluded = self.excluded_panes();
+        let mapping = classify::dump_pane_ids(dump)
+            .into_iter()
+            .map(|(pid, pane)| {
+                let tab = match self.pane_tabs.get(&pane).and_then(|t| self.tab_names.get(t)) {
+                    Some(name) => format!("tab {:?}", name),
+                    None => "tab unknown".to_string(),
+                };
+                let counted = if excluded.contains(&pane) { "ignored" } else { "counted" };
+                format!("{} -> pane {} ({}, {})", pid, pane, tab, counted)
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        if mapping != self.last_pane_mapping {
+            self.log(format!("pane mapping: {}", mapping));
+            self.last_pane_mapping = mapping;
+        }
+    }
+
+    /// Number of terminal panes: zellij's own count once a PaneUpdate has arrived, else
+    /// the number of pane shells the last idle check found.
+    fn pane_total(&self) -> usize {
+        self.zellij_pane_count.unwrap_or(self.shell_count)
+    }
+
+    fn log(&mut self, msg: String) {
+        self.log_event("log", serde_json::json!({}), msg);
+    }
+
+    /// Logs `msg` as-is, or with log_format json, as one JSON object tagged with
+    /// `event` that carries the poll counters plus the fields in `extra`.
+    fn log_event(&mut self, event: &str, extra: serde_json::Value, msg: String) {
+        let line = if self.log_json {
+            let mut obj = serde_json::json!({
+                "ts": unix_now(),
+                "event": event,
+                "poll": self.poll_count,
+                "idle_elapsed": self.idle_elapsed_secs,
+                "countdown_remaining": self.countdown_remaining,
+                "active_count": self.active_pane_count,
+                "msg": msg,
+            });
+            if let (Some(obj), serde_json::Value::Object(extra)) = (obj.as_object_mut(), extra) {
+                obj.extend(extra);
+            }
+            obj.to_string()
+        } else {
+            msg
+        };
+        eprintln!("zellij-idle: {}", line);
+        self.log_buffer.push(line);
+    }
+
+    fn flush_logs(&mut self) {
+        if self.log_buffer.is_empty() {
+            return;
+        }
+        let content = self.log_buffer.join("\n");
+        self.log_buffer.clear();
+        let max_bytes = self.log_max_bytes.to_string();
+        let mut context = BTreeMap::new();
+        context.insert("command".to_string(), "log".to_string());
+        run_command(
+            &[
+                "bash",
+                "-c",
+                LOG_FLUSH_SCRIPT,
+                "_",
+                &content,
+                &self.log_file,
+                &max_bytes,
+                if self.log_json { "json" } else { "text" },
+            ],
+            context,
+        );
+    }
+
+    /// Whether this tick should scan /proc: always, unless event_driven, in which case
+    /// only after a zellij change or once safety_poll_secs has passed since the last scan.
+    fn idle_check_due(&self) -> bool {
+        if !self.event_driven || self.idle_check_requested {
+            return true;
+        }
+        self.clock_secs - self.last_idle_check_secs >= self.safety_poll_secs
+    }
+
+    /// PIDs 0 and 1 are what a broken get_plugin_ids looks like in some containers.
+    fn zellij_pid_valid(&self) -> bool {
+        self.zellij_pid > 1
+    }
+
+    fn run_idle_check(&mut self) {
+        if !self.zellij_pid_valid() {
+            return;
+        }
+        if self.idle_check_in_flight {
+            if self.poll_count.saturating_sub(self.idle_check_started_poll) < IDLE_CHECK_STALE_POLLS {
+                self.log(format!(
+                    "poll #{}: previous idle check still running, skipping",
+                    self.poll_count
+                ));
+                return;
+            }
+            self.log(format!(
+                "poll #{}: idle check from poll #{} never reported back, starting a new one",
+                self.poll_count, self.idle_check_started_poll
+            ));
+        }
+        self.idle_check_in_flight = true;
+        self.idle_check_started_poll = self.poll_count;
+        let mut context = BTreeMap::new();
+        context.insert("command".to_string(), "idle_check".to_string());
+        let command = self.idle_check_command();
+        run_command(&command.iter().map(String::as_str).collect::<Vec<_>>(), context);
+    }
+
+    /// Starts SELFCHECK_SCRIPT; the report follows in the selfcheck RunCommandResult.
+    fn run_selfcheck(&mut self) {
+        self.log("running selfcheck".to_string());
+        let custom = !self.suspend_command.is_empty();
+        let action = self.effective_suspend_action();
+        let cli = if custom || action == "none" || action == "lock" {
+            ""
+        } else {
+            self.suspend_cli_path()
+        };
+        let cloud = match action {
+            _ if custom => "",
+            "stop" | "suspend" => "gce",
+            action if action.starts_with("ec2-") => "aws",
+            action if action.starts_with("azure-") => "azure",
+            _ => "",
+        };
+        let mut command = vec![
+            "bash".to_string(),
+            "-c".to_string(),
+            SELFCHECK_SCRIPT.to_string(),
+            "_".to_string(),
+            cli.to_string(),
+            cloud.to_string(),
+        ];
+        command.extend(self.idle_check_command());
+        let mut context = BTreeMap::new();
+        context.insert("command".to_string(), "selfcheck".to_string());
+        run_command(&command.iter().map(String::as_str).collect::<Vec<_>>(), context);
+    }
+
+    /// Turns SELFCHECK_SCRIPT output into report lines, adding the plugin's own checks
+    /// and the classification of the dry idle scan.
+    fn selfcheck_report(&self, output: &str) -> Vec<String> {
+        let (checks, dump) = output.split_once("\n---\n").unwrap_or((output, ""));
+        let mut report: Vec<String> = checks
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(str::to_string)
+            .collect();
+        if self.suspend_action == "auto" && self.detected_provider.is_none() {
+            report.push("FAIL: suspend_action auto hasn't detected a cloud provider yet".to_string());
+        } else if !self.suspend_command.is_empty() {
+            report.push(format!("skip: custom suspend_command {:?} not checked", self.suspend_command));
+        }
+        if !self.zellij_pid_valid() {
+            report.push(format!("FAIL: zellij_pid is {}; idle detection is disabled", self.zellij_pid));
+            return report;
+        }
+        let lines = if self.custom_idle_check.is_empty() {
+            self.classify(dump)
+        } else {
+            let (lines, malformed) = classify::check_custom_output(dump);
+            if !malformed.is_empty() {
+                report.push(format!("FAIL: idle_check_command printed malformed lines: {:?}", malformed));
+            }
+            lines
+        };
+        let panes: Vec<String> = lines
+            .lines()
+            .filter(|line| !line.starts_with("idle:-:") && !line.starts_with("active:-:"))
+            .map(str::to_string)
+            .collect();
+        if panes.is_empty() {
+            report.push("FAIL: idle scan found no pane shells".to_string());
+        } else {
+            report.push(format!("ok: idle scan: {}", panes.join(", ")));
+        }
+        report
+    }
+
+    /// Handles the idle check's output. A scan that failed says nothing about the panes,
+    /// so it's logged and the previous state holds until the next one.
+    fn handle_idle_check_result(&mut self, exit_code: Option<i32>, stdout: &[u8], stderr: &[u8]) {
+        self.idle_check_in_flight = false;
+        if exit_code != Some(0) {
+            self.log(format!(
+                "poll #{}: idle check failed (exit {:?}), keeping the previous state: {}",
+                self.poll_count,
+                exit_code,
+                String::from_utf8_lossy(stderr).trim()
+            ));
+            return;
+        }
+        let dump = String::from_utf8_lossy(stdout);
+        if !self.custom_idle_check.is_empty() {
+            let (lines, malformed) = classify::check_custom_output(&dump);
+            if malformed != self.custom_idle_check_malformed {
+                if !malformed.is_empty() {
+                    self.log(format!(
+                        "idle_check_command: ignoring {} malformed line(s), expected STATE:PID:DETAIL with STATE idle or active: {:?}",
+                        malformed.len(),
+                        malformed
+                    ));
+                }
+                self.custom_idle_check_malformed = malformed;
+            }
+            self.parse_idle_check_output(lines.as_bytes());
+            self.write_heartbeat();
+            return;
+        }
+        self.update_agent_cpu(&dump);
+        let mut lines = self.classify(&dump);
+        if self.cpu_busy(&dump) {
+            lines.push_str("active:-:cpu-busy\n");
+        }
+        if self.gpu_busy(&dump) {
+            lines.push_str("active:-:gpu-busy\n");
+        }
+        if self.net_busy(&dump) {
+            lines.push_str("active:-:net-busy\n");
+        }
+        if self.container_busy(&dump) {
+            lines.push_str("active:-:container-busy\n");
+        }
+        if self.desktop_active(&dump) {
+            lines.push_str("active:-:desktop-active\n");
+        }
+        if self.too_many_panes(&dump) {
+            lines.push_str("active:-:too-many-panes\n");
+        }
+        self.parse_idle_check_output(lines.as_bytes());
+        self.log_pane_mapping(&dump);
+        self.update_power_source(&dump);
+        self.update_child_pids(&dump);
+        self.write_heartbeat();
+    }
+
+    /// The contents of heartbeat_file: when the last successful poll was, and its result.
+    fn heartbeat(&self) -> String {
+        format!(
+            "unix={}\npoll={}\nstate={}\n",
+            unix_now() as u64,
+            self.poll_count,
+            self.state_name()
+        )
+    }
+
+    fn write_heartbeat(&self) {
+        if self.heartbeat_file.is_empty() {
+            return;
+        }
+        let mut context = BTreeMap::new();
+        context.insert("command".to_string(), "heartbeat".to_string());
+        run_command(
+            &["bash", "-c", HEARTBEAT_SCRIPT, "_", &self.heartbeat_file, &self.heartbeat()],
+            context,
+        );
+    }
+
+    /// Caches the pane shell PIDs a dump looked up, or counts a dump that reused them.
+    /// Dropping the cache when a cached shell is gone makes the next dump look them up.
+    /// Only the macOS dump takes the cache; on Linux there's no pgrep for it to save.
+    fn update_child_pids(&mut self, dump: &str) {
+        if !self.platform_macos {
+            return;
+        }
+        if let Some(pids) = classify::dump_value(dump, "children") {
+            let pids: Vec<u32> = pids.split_whitespace().filter_map(|p| p.parse().ok()).collect();
+            self.child_pid_lookups += 1;
+            if self.child_pids.as_ref() != Some(&pids) {
+                self.log(format!(
+                    "pane shells: {:?}; the cache has saved {} of {} pgrep runs",
+                    pids,
+                    self.child_pid_reuses,
+                    self.child_pid_reuses + self.child_pid_lookups
+                ));
+            }
+            self.child_pids = Some(pids);
+            self.child_pids_age = 0;
+            return;
+        }
+        let Some(cached) = &self.child_pids else {
+            return;
+        };
+        self.child_pid_reuses += 1;
+        self.child_pids_age += 1;
+        let found = dump.lines().filter(|line| line.starts_with("stat:")).count();
+        if found < cached.len() {
+            self.child_pids = None;
+        }
+    }
+
+    /// Classifies an idle check dump with the configured options.
+    fn classify(&self, dump: &str) -> String {
+        let excluded_panes = self.excluded_panes();
+        let opts = ClassifyOptions {
+            claude_code_idle_detection: self.claude_code_idle_detection,
+            claude_working_cpu_threshold: self.claude_working_cpu_threshold,
+            agent_cpu_pct: &self.agent_cpu_pct,
+            agent_process_patterns: &self.agent_process_patterns,
+            ignore_processes: &self.ignore_processes,
+            ignore_patterns: &self.ignore_patterns,
+            include_processes: &self.include_processes,
+            detect_ssh_sessions: self.detect_ssh_sessions,
+            mosh_idle_after_secs: self.mosh_idle_after_secs,
+            protect_editors: self.protect_editors,
+            treat_stdin_wait_as_idle: self.treat_stdin_wait_as_idle,
+            excluded_panes: &excluded_panes,
+        };
+        classify::classify_dump(dump, &opts)
+    }
+
+    /// The command line of the idle check: PROC_DUMP_SCRIPT and its arguments, or
+    /// idle_check_command with the zellij PID as $1.
+    fn idle_check_command(&self) -> Vec<String> {
+        let pid_str = self.zellij_pid.to_string();
+        if !self.custom_idle_check.is_empty() {
+            return vec![
+                "bash".to_string(),
+                "-c".to_string(),
+                self.custom_idle_check.clone(),
+                "_".to_string(),
+                pid_str,
+            ];
+        }
+        let detect_ssh = if self.detect_ssh_sessions {
+            "true"
+        } else {
+            "false"
+        };
+        let sample_cpu = if self.cpu_active_threshold_pct > 0.0 {
+            "true"
+        } else {
+            "false"
+        };
+        let recurse_mux = if self.recurse_multiplexers {
+            "true"
+        } else {
+            "false"
+        };
+        let detect_gpu = if self.detect_gpu { "true" } else { "false" };
+        let detect_net = if self.detect_network_active {
+            "true"
+        } else {
+            "false"
+        };
+        let protect_editors = if self.protect_editors {
+            "true"
+        } else {
+            "false"
+        };
+        let battery_only = if self.battery_only { "true" } else { "false" };
+        let container_engines = match (&self.container_engines, self.detect_containers) {
+            (_, false) => "",
+            (Some(engines), true) => engines.as_str(),
+            (None, true) => "probe",
+        };
+        let stdin_wait = if self.treat_stdin_wait_as_idle {
+            "true"
+        } else {
+            "false"
+        };
+        let system_idle = if self.respect_system_idle {
+            "true"
+        } else {
+            "false"
+        };
+        let children = match &self.child_pids {
+            Some(pids) if self.child_pids_age < self.child_pid_refresh_polls => pids
+                .iter()
+                .map(|pid| pid.to_string())
+                .collect::<Vec<_>>()
+                .join(" "),
+            _ => String::new(),
+        };
+        let fg_trees = if self.claude_working_cpu_threshold > 0.0 {
+            "true"
+        } else {
+            "false"
+        };
+        let max_panes = match self.max_panes_for_detection {
+            0 => String::new(),
+            max => max.to_string(),
+        };
+        let script = if self.platform_macos {
+            MACOS_PROC_DUMP_SCRIPT
+        } else {
+            PROC_DUMP_SCRIPT
+        };
+        [
+            "bash",
+            "-c",
+            script,
+            "_",
+            &pid_str,
+            detect_ssh,
+            sample_cpu,
+            recurse_mux,
+            detect_gpu,
+            detect_net,
+            protect_editors,
+            battery_only,
+            stdin_wait,
+            container_engines,
+            &children,
+            system_idle,
+            &max_panes,
+            fg_trees,
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect()
+    }
+
+    fn trigger_suspend(&mut self) {
+        if self.suspend_command_sent {
+            return;
+        }
+        if !self.enabled {
+            self.log("disabled, not suspending".to_string());
+            self.suspend_triggered = false;
+            return;
+        }
+        self.suspend_command_sent = true;
+
+        if self.dump_layout_before_suspend {
+            if self.dry_run {
+                self.log("dry_run: would save the session layout".to_string());
+            } else {
+                // Carries on with run_pre_suspend_command from the dump_layout RunCommandResult
+                let mut context = BTreeMap::new();
+                context.insert("command".to_string(), "dump_layout".to_string());
+                let session = self.session_name.clone().unwrap_or_default();
+                run_command(
+                    &["bash", "-c", DUMP_LAYOUT_SCRIPT, "_", &self.layout_dump_path, &session],
+                    context,
+                );
+                return;
+            }
+        }
+        self.run_pre_suspend_command();
+    }
+
+    /// Runs post_resume_command, if there is one, for a load that follows a suspend.
+    fn run_post_resume_command(&mut self) {
+        if self.post_resume_command.is_empty() {
+            return;
+        }
+        self.log(format!(
+            "back from suspend, running post_resume_command {:?}",
+            self.post_resume_command
+        ));
+        let mut context = BTreeMap::new();
+        context.insert("command".to_string(), "post_resume".to_string());
+        run_command(&["bash", "-c", &self.post_resume_command], context);
+    }
+
+    /// Runs pre_suspend_command if there is one, and the suspend itself otherwise.
+    fn run_pre_suspend_command(&mut self) {
+        // The pre-suspend hook runs first; the suspend itself only follows once it exits 0
+        // (see the pre_suspend RunCommandResult). dry_run skips the hook along with the rest.
+        if !self.pre_suspend_command.is_empty() {
+            if self.dry_run {
+                self.log(format!(
+                    "dry_run: would run pre_suspend_command {:?}",
+                    self.pre_suspend_command
+                ));
+            } else {
+                self.log(format!(
+                    "running pre_suspend_command {:?}",
+                    self.pre_suspend_command
+                ));
+                let mut context = BTreeMap::new();
+                context.insert("command".to_string(), "pre_suspend".to_string());
+                run_command(&["bash", "-c", &self.pre_suspend_command], context);
+                return;
+            }
+        }
+        self.run_suspend_command();
+    }
+
+    /// Runs the configured suspend command, or just logs it under dry_run.
+    fn run_suspend_command(&mut self) {
+        // Disabled while a pre_suspend_command or retry backoff was pending
+        if !self.enabled {
+            self.log("disabled, not suspending".to_string());
+            self.suspend_triggered = false;
+            self.suspend_command_sent = false;
+            return;
+        }
+        if let Some(action) = &self.bad_suspend_action {
+            self.log(format!("unknown suspend_action {:?}, not suspending", action));
+            self.restart_idle_cycle();
+            return;
+        }
+        if let Some(reason) = self.suspend_unavailable_reason() {
+            self.run_fallback_action(&reason);
+            return;
+        }
+        if self.effective_suspend_action() == "lock" {
+            self.lock_session();
+            return;
+        }
+        // With a fallback after it, a failed GCE suspend is the chain's to handle
+        let has_fallback = self.suspend_chain_index + 1 < self.suspend_chain.len();
+        let systemd_target = systemd_target(self.effective_suspend_action()).map(str::to_string);
+        let (script, action) = if let Some(target) = &systemd_target {
+            (LOCAL_SUSPEND_SCRIPT, target.as_str())
+        } else {
+            match self.effective_suspend_action() {
+                "stop" => (SUSPEND_SCRIPT, "stop"),
+                "suspend" if has_fallback => (SUSPEND_SCRIPT, "suspend-only"),
+                "ec2-stop" => (AWS_SUSPEND_SCRIPT, "stop"),
+                "ec2-hibernate" => (AWS_SUSPEND_SCRIPT, "hibernate"),
+                "azure-deallocate" => (AZURE_SUSPEND_SCRIPT, "deallocate"),
+                "azure-stop" => (AZURE_SUSPEND_SCRIPT, "stop"),
+                "shutdown" => (LOCAL_SUSPEND_SCRIPT, "poweroff"),
+                "systemd-suspend" => (LOCAL_SUSPEND_SCRIPT, "suspend"),
+                _ => (SUSPEND_SCRIPT, "suspend"),
+            }
+        };
+
+        // A custom suspend_command replaces the built-in provider scripts entirely.
+        // It still receives the resolved action as $1.
+        let (script, description) = if !self.suspend_command.is_empty() {
+            (
+                self.suspend_command.clone(),
+                format!("custom suspend_command {:?}", self.suspend_command),
+            )
+        } else if self.suspend_action == "auto" && self.detected_provider.is_none() {
+            // Not a real "none": the probe may still answer, so try again next idle cycle
+            self.log(
+                "suspend_action auto: cloud provider probe hasn't answered yet, not suspending this cycle"
+                    .to_string(),
+            );
+            self.restart_idle_cycle();
+            return;
+        } else if self.effective_suspend_action() == "none" {
+            self.log(format!(
+                "suspend_action is {:?}, skipping suspend command",
+                self.suspend_action
+            ));
+            // Possibly the end of a chain, so start over as the other actions would
+            self.restart_idle_cycle();
+            return;
+        } else {
+            (
+                script.to_string(),
+                format!(
+                    "built-in script for suspend_action={}",
+                    self.effective_suspend_action()
+                ),
+            )
+        };
+
+        if self.dry_run {
+            self.log(format!(
+                "dry_run: would run `{}bash -c <{}> _ {}`",
+                self.suspend_command_prefix()
+                    .iter()
+                    .map(|arg| format!("{} ", arg))
+                    .collect::<String>(),
+                description,
+                action
+            ));
+            return;
+        }
+
+        // A fallback is part of the same attempt
+        let first_in_chain = self.suspend_chain_index == 0;
+        if first_in_chain {
+            self.suspend_attempts += 1;
+            if self.suspend_chain.len() > 1 {
+                self.log(format!("suspend chain: {}", self.suspend_chain.join(" -> ")));
+            }
+        }
+        let first_try = first_in_chain && self.suspend_attempts == 1;
+        if first_try {
+            self.savings.record_suspend(unix_now());
+            self.save_savings();
+        }
+        self.log_event(
+            "suspend",
+            serde_json::json!({ "action": action, "attempt": self.suspend_attempts }),
+            format!(
+                "triggering suspend (action={}, attempt {}) via {}",
+                action, self.suspend_attempts, description
+            ),
+        );
+        self.suspend_run += 1;
+        self.suspend_started_secs = Some(self.clock_secs);
+        let mut context = BTreeMap::new();
+        context.insert("command".to_string(), "suspend".to_string());
+        context.insert("run".to_string(), self.suspend_run.to_string());
+        let mut command = self.suspend_command_prefix();
+        command.extend(["bash", "-c", &script, "_", action]);
+        if self.suspend_command.is_empty() {
+            command.push(self.suspend_cli_path());
+        }
+        run_command(&command, context);
+        // Retries and fallbacks of the same suspend don't make another record
+        if !self.webhook_on_success && first_try {
+            self.post_suspend_webhook();
+        }
+    }
+
+    /// Carries on after a suspend attempt that failed or timed out: the next action in
+    /// the chain, a retry after retry_backoff_secs, or giving up until the next idle cycle.
+    fn suspend_attempt_failed(&mut self) {
+        if !self.suspend_triggered {
+            // Activity cancelled the suspend while it was running; nothing to retry
+            self.savings.cancel_suspend();
+            self.save_savings();
+        } else if self.suspend_chain_index + 1 < self.suspend_chain.len() {
+            let failed = self.effective_suspend_action().to_string();
+            self.suspend_chain_index += 1;
+            self.suspend_action = self.suspend_chain[self.suspend_chain_index].clone();
+            self.log(format!(
+                "suspend_action {} failed, falling back to {}",
+                failed, self.suspend_action
+            ));
+            self.run_suspend_command();
+        } else if self.suspend_attempts <= self.suspend_max_retries {
+            // A retry goes through the whole chain again
+            self.reset_suspend_chain();
+            self.suspend_retry_at_unix = Some(unix_now() + self.retry_backoff_secs);
+            self.log(format!("retrying suspend in {}s", self.retry_backoff_secs));
+        } else {
+            self.log(format!(
+                "giving up on suspend after {} attempts; trying again after the next idle cycle",
+                self.suspend_attempts
+            ));
+            self.suspend_disabled_reason =
+                Some(format!("gave up after {} attempts", self.suspend_attempts));
+            self.savings.cancel_suspend();
+            self.save_savings();
+            self.restart_idle_cycle();
+        }
+    }
+
+    /// What the suspend command runs under: `sudo -n [-u user]` for suspend_run_as, then
+    /// `env` with suspend_env. sudo resets the environment, so env has to come after it.
+    fn suspend_command_prefix(&self) -> Vec<&str> {
+        let mut prefix = Vec::new();
+        if !self.suspend_run_as.is_empty() {
+            prefix.extend(["sudo", "-n"]);
+            if self.suspend_run_as != "root" {
+                prefix.extend(["-u", self.suspend_run_as.as_str()]);
+            }
+        }
+        if !self.suspend_env.is_empty() {
+            prefix.push("env");
+            prefix.extend(self.suspend_env.iter().map(String::as_str));
+        }
+        prefix
+    }
+
+    /// Why the box has no way to suspend, once the load-time checks have found out:
+    /// the suspend CLI is missing, or suspend_action "auto" found no cloud. None while
+    /// a suspend_action later in the chain could still work.
+    fn suspend_unavailable_reason(&self) -> Option<String> {
+        if !self.suspend_command.is_empty()
+            || self.suspend_chain_index + 1 < self.suspend_chain.len()
+        {
+            return None;
+        }
+        if self.suspend_action == "auto"
+            && self
+                .detected_provider
+                .as_deref()
+                .is_some_and(|provider| provider.is_empty() || provider == "none")
+        {
+            return Some("suspend_action auto found no cloud provider".to_string());
+        }
+        if self.cli_missing {
+            return Some(format!("{} CLI not found", self.suspend_cli()));
+        }
+        None
+    }
+
+    fn log_suspend_unavailable(&mut self) {
+        if let Some(reason) = self.suspend_unavailable_reason() {
+            self.log(format!(
+                "no suspend mechanism available ({}); idle cycles will end in fallback_action {:?}",
+                reason, self.fallback_action
+            ));
+        }
+    }
+
+    /// Does fallback_action in place of a suspend the box can't do.
+    fn run_fallback_action(&mut self, reason: &str) {
+        let command = self
+            .fallback_action
+            .strip_prefix("command:")
+            .map(|command| command.trim().to_string());
+        let command = command.as_deref();
+        if self.dry_run {
+            self.log(match (command, self.fallback_action.as_str()) {
+                (Some(command), _) => format!("dry_run: would run fallback_action command {:?}", command),
+                (None, "detach") => "dry_run: would detach all clients (fallback_action)".to_string(),
+                _ => format!("dry_run: no suspend mechanism available ({}), would not suspend", reason),
+            });
+            return;
+        }
+        self.log_event(
+            "fallback",
+            serde_json::json!({ "fallback_action": self.fallback_action, "reason": reason }),
+            match (command, self.fallback_action.as_str()) {
+                (Some(command), _) => format!(
+                    "no suspend mechanism available ({}); running fallback_action command {:?}",
+                    reason, command
+                ),
+                (None, "detach") => format!(
+                    "no suspend mechanism available ({}); detaching all clients instead",
+                    reason
+                ),
+                _ => format!(
+                    "NOT SUSPENDING: no suspend mechanism available ({}); set suspend_action, suspend_command or fallback_action",
+                    reason
+                ),
+            },
+        );
+        match (command, self.fallback_action.as_str()) {
+            (Some(command), _) => {
+                // Carries on from the fallback RunCommandResult
+                let mut context = BTreeMap::new();
+                context.insert("command".to_string(), "fallback".to_string());
+                run_command(&["bash", "-c", command], context);
+            }
+            (None, "detach") => {
+                disconnect_other_clients();
+                detach();
+                self.restart_idle_cycle();
+            }
+            _ => self.restart_idle_cycle(),
+        }
+    }
+
+    /// suspend_action "lock": runs lock_command, or without one, detaches every client,
+    /// then starts a new idle cycle since the box stays up.
+    fn lock_session(&mut self) {
+        if self.dry_run {
+            self.log(match self.lock_command.as_str() {
+                "" => "dry_run: would detach all clients".to_string(),
+                command => format!("dry_run: would run lock_command {:?}", command),
+            });
+            return;
+        }
+        self.log_event(
+            "lock",
+            serde_json::json!({ "lock_command": self.lock_command }),
+            match self.lock_command.as_str() {
+                "" => "locking the session: detaching all clients".to_string(),
+                command => format!("locking the session via lock_command {:?}", command),
+            },
+        );
+        if self.lock_command.is_empty() {
+            disconnect_other_clients();
+            detach();
+            self.restart_idle_cycle();
+        } else {
+            // Carries on from the lock RunCommandResult
+            let mut context = BTreeMap::new();
+            context.insert("command".to_string(), "lock".to_string());
+            run_command(&["bash", "-c", &self.lock_command], context);
+        }
+    }
+
+    /// Records the suspend at post_suspend_webhook, if set. The result is only logged.
+    fn post_suspend_webhook(&self) {
+        if self.post_suspend_webhook.is_empty() {
+            return;
+        }
+        let idle_secs = (self.idle_elapsed_secs as u64).to_string();
+        let mut context = BTreeMap::new();
+        context.insert("command".to_string(), "post_suspend".to_string());
+        run_command(
+            &[
+                "bash",
+                "-c",
+                POST_SUSPEND_WEBHOOK_SCRIPT,
+                "_",
+                &self.post_suspend_webhook,
+                self.effective_suspend_action(),
+                &idle_secs,
+            ],
+            context,
+        );
+    }
+
+    /// Handles a keypress (or any other input routed to the plugin). Returns whether
+    /// to re-render.
+    ///
+    /// InputReceived carries no key data in zellij-tile 0.43, so typing can't be told
+    /// apart from focus changes or synthetic input. A burst only needs handling once,
+    /// though: after the first event there's no idle time or countdown left to reset.
+    ///
+    /// With input_debounce_secs, input within that long of the last accepted input is
+    /// dropped, so a terminal's steady trickle of automatic reports (cursor position
+    /// and the like) counts at most once per window. It's never applied once a
+    /// countdown or suspend is pending: input then is someone trying to stop it.
+    fn handle_input(&mut self) -> bool {
+        let now = unix_now();
+        let pending = self.countdown_active || self.suspend_triggered || self.awaiting_confirm;
+        let debounced = self.input_debounce_secs > 0.0
+            && !pending
+            && now - self.last_accepted_input_unix < self.input_debounce_secs;
+        if debounced {
+            if self.input_noise_events == 0 {
+                self.log(format!(
+                    "input within {}s of the last input, treating it as noise (input_debounce_secs)",
+                    self.input_debounce_secs
+                ));
+            }
+            self.input_noise_events += 1;
+            return false;
+        }
+        self.last_accepted_input_unix = now;
+        if self.awaiting_confirm {
+            // Cancels on the next tick unless zellij-idle-confirm follows
+            self.confirm_input_unix.get_or_insert(now);
+            return false;
+        }
+        // Someone's here to see it, so the bar can go back to normal
+        if self.bad_suspend_action.is_none() {
+            self.suspend_disabled_reason = None;
+        }
+        let pending = self.is_idle || self.countdown_active || self.suspend_triggered;
+        if !pending && now - self.last_input_unix < INPUT_BURST_SECS {
+            return false;
+        }
+        self.last_input_unix = now;
+
+        if self.maxtime_countdown {
+            // The session cap can't be typed away, but it can be pushed back once
+            let can_extend = self.countdown_active
+                && !self.session_extended
+                && self.max_session_extension_secs > 0.0;
+            if can_extend {
+                self.log(format!(
+                    "input received, extending max session by {}s",
+                    self.max_session_extension_secs
+                ));
+                self.session_extended = true;
+                self.maxtime_countdown = false;
+                self.countdown_active = false;
+                self.countdown_remaining = 0.0;
+            }
+        } else if !self.keypress_resets_idle {
+            // Still idle, so the countdown starts over from full on the next poll
+            if self.suspend_triggered {
+                self.log("input received, cancelling suspend (idle timer kept)".to_string());
+                self.cancel_suspend();
+            } else if self.countdown_active {
+                self.log("input received, cancelling countdown (idle timer kept)".to_string());
+                self.countdown_active = false;
+                self.countdown_remaining = 0.0;
+            }
+        } else {
+            if self.countdown_active {
+                self.log("input received, cancelling countdown".to_string());
+            } else if self.is_idle {
+                self.log("input received, resetting idle timer".to_string());
+            }
+            self.reset_activity();
+        }
+        true
+    }
+
+    /// Reads the boot time for min_uptime_secs, unless it's off or already known.
+    fn fetch_uptime(&self) {
+        if self.min_uptime_secs <= 0.0 || self.boot_unix.is_some() {
+            return;
+        }
+        let mut context = BTreeMap::new();
+        context.insert("command".to_string(), "uptime".to_string());
+        run_command(&["cat", "/proc/uptime"], context);
+    }
+
+    /// suspend_action, with "auto" resolved to the detected provider's default action.
+    /// Until the probe answers, and when no provider was found, that's "none".
+    fn effective_suspend_action(&self) -> &str {
+        if self.suspend_action != "auto" {
+            return &self.suspend_action;
+        }
+        match self.detected_provider.as_deref() {
+            Some("gce") => "suspend",
+            Some("aws") => "ec2-stop",
+            Some("azure") => "azure-deallocate",
+            _ => "none",
+        }
+    }
+
+    /// Checks that the CLI for the suspend action is on PATH. For suspend_action "auto",
+    /// probes for the cloud provider first; the check follows once that answers.
+    fn check_suspend_cli(&self) {
+        if !self.suspend_command.is_empty() || self.bad_suspend_action.is_some() {
+            return;
+        }
+        let mut context = BTreeMap::new();
+        if self.suspend_action == "auto" && self.detected_provider.is_none() {
+            context.insert("command".to_string(), "detect_provider".to_string());
+            run_command(&["bash", "-c", DETECT_PROVIDER_SCRIPT], context);
+        } else if !matches!(self.effective_suspend_action(), "none" | "lock") {
+            context.insert("command".to_string(), "cli_check".to_string());
+            run_command(&["which", self.suspend_cli_path()], context);
+        }
+    }
+
+    /// The CLI binary that the configured suspend_action shells out to.
+    fn suspend_cli(&self) -> &'static str {
+        match self.effective_suspend_action() {
+            "shutdown" => "shutdown",
+            "systemd-suspend" => "systemctl",
+            action if systemd_target(action).is_some() => "systemctl",
+            action if action.starts_with("ec2-") => "aws",
+            action if action.starts_with("azure-") => "az",
+            _ => "gcloud",
+        }
+    }
+
+    /// The suspend CLI as configured by gcloud_path, aws_path or az_path.
+    fn suspend_cli_path(&self) -> &str {
+        match self.suspend_cli() {
+            "gcloud" => &self.gcloud_path,
+            "aws" => &self.aws_path,
+            "az" => &self.az_path,
+            cli => cli,
+        }
+    }
+
+    fn parse_idle_check_output(&mut self, stdout: &[u8]) {
+        let output = String::from_utf8_lossy(stdout);
+        let mut active_count = 0;
+        let mut active_procs = Vec::new();
+        let mut idle_details = Vec::new();
+        let mut active_details = Vec::new();
+        let mut total_panes = 0;
+
+        // Lines with a pid of "-" are box-wide activity signals (e.g. an ssh session)
+        // rather than panes: they keep the box awake but don't count toward totals.
+        let mut box_signals = Vec::new();
+
+        for line in output.lines() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let parts: Vec<&str> = line.splitn(3, ':').collect();
+            if parts.len() == 3 && parts[1] == "-" {
+                if parts[0] == "active" {
+                    box_signals.push(parts[2].trim().to_string());
+                }
+                continue;
+            }
+
+            // Malformed lines aren't panes at all
+            if parts.len() < 3 {
+                continue;
+            }
+            total_panes += 1;
+
+            if parts[0] == "active" {
+                active_count += 1;
+                let proc_name = parts[2].trim();
+                active_details.push(format!("pid={} fg={}", parts[1], proc_name));
+                if !proc_name.is_empty() && proc_name != "unknown" {
+                    active_procs.push(proc_name.to_string());
+                }
+            } else {
+                idle_details.push(format!("pid={} {}", parts[1], parts[2].trim()));
+            }
+        }
+
+        let idle_ratio = if total_panes > 0 {
+            (total_panes - active_count) as f64 / total_panes as f64
+        } else {
+            0.0
+        };
+        self.log_event(
+            "poll",
+            serde_json::json!({
+                "active_count": active_count,
+                "total_panes": total_panes,
+                "idle_ratio": idle_ratio,
+                "zellij_panes": self.zellij_pane_count,
+                "active": active_details,
+                "idle": idle_details,
+                "box": box_signals,
+            }),
+            format!(
+                "poll #{}: {}/{} pane shells active{}{} | active=[{}] idle=[{}] box=[{}]",
+                self.poll_count,
+                active_count,
+                total_panes,
+                match self.zellij_pane_count {
+                    Some(panes) if panes != total_panes => {
+                        format!(" (zellij reports {} terminal panes)", panes)
+                    }
+                    _ => String::new(),
+                },
+                if self.idle_ratio_threshold < 1.0 {
+                    format!(", idle ratio {:.2}", idle_ratio)
+                } else {
+                    String::new()
+                },
+                active_details.join(", "),
+                idle_details.join(", "),
+                box_signals.join(", ")
+            ),
+        );
+
+        if total_panes == 0 {
+            self.empty_poll_streak += 1;
+            if self.empty_poll_streak == NO_PANES_POLLS {
+                self.log(format!(
+                    "warning: no panes found in {} consecutive polls; is zellij_pid {} right?",
+                    NO_PANES_POLLS, self.zellij_pid
+                ));
+            }
+        } else {
+            self.empty_poll_streak = 0;
+        }
+
+        let was_idle = self.is_idle;
+        // With idle_ratio_threshold below 1, a few always-busy panes don't hold the box up
+        let keeping_awake = !box_signals.is_empty()
+            || (active_count > 0 && idle_ratio < self.idle_ratio_threshold);
+        if total_panes > 0 || keeping_awake {
+            if self.activity_history.len() == SPARKLINE_POLLS {
+                self.activity_history.pop_front();
+            }
+            self.activity_history.push_back(keeping_awake);
+        }
+        active_procs.extend(box_signals);
+        self.active_reason = dominant_reason(&active_procs);
+        self.active_pane_count = active_count;
+        self.shell_count = total_panes;
+        self.active_processes = collapse_duplicates(active_procs);
+
+        if !keeping_awake && total_panes > 0 {
+            self.idle_poll_streak += 1;
+            let going_idle = !self.is_idle && !self.is_snoozed();
+            if going_idle && self.idle_poll_streak < self.consecutive_idle_polls {
+                // A moment between two commands can look idle; wait for it to last
+                self.log(format!(
+                    "idle poll {}/{}, not idle yet",
+                    self.idle_poll_streak, self.consecutive_idle_polls
+                ));
+            } else if going_idle {
+                self.is_idle = true;
+                let mut reason = if active_count == 0 {
+                    format!("all {} panes idle", total_panes)
+                } else {
+                    format!(
+                        "{}/{} panes idle, ratio {:.2} >= {}",
+                        total_panes - active_count,
+                        total_panes,
+                        idle_ratio,
+                        self.idle_ratio_threshold
+                    )
+                };
+                if self.consecutive_idle_polls > 1 {
+                    reason.push_str(&format!(", {} polls in a row", self.idle_poll_streak));
+                }
+                self.log_event(
+                    "idle",
+                    serde_json::json!({ "total_panes": total_panes }),
+                    format!("-> IDLE ({})", reason),
+                );
+            }
+        } else if keeping_awake {
+            if was_idle || (self.countdown_active && !self.maxtime_countdown) {
+                self.log_event(
+                    "active",
+                    serde_json::json!({ "keeping_awake": self.active_processes }),
+                    format!("-> ACTIVE (keeping awake: {})", self.active_processes.join(", ")),
+                );
             }
             self.is_idle = false;
+            self.idle_poll_streak = 0;
             self.idle_elapsed_secs = 0.0;
-            self.last_activity_poll_count = self.poll_count;
-            self.countdown_active = false;
+            self.last_activity_secs = self.clock_secs;
+            self.snap_back_poll_interval();
+            if !self.maxtime_countdown {
+                self.countdown_active = false;
+            }
+        }
+        // If total_panes == 0, keep current state (startup or no terminal panes yet)
+    }
+}
+
+/// Parses a 24h `HH:MM` string into minutes since midnight.
+fn parse_hhmm(s: &str) -> Option<u32> {
+    let (h, m) = s.trim().split_once(':')?;
+    let h: u32 = h.parse().ok()?;
+    let m: u32 = m.parse().ok()?;
+    if h < 24 && m < 60 {
+        Some(h * 60 + m)
+    } else {
+        None
+    }
+}
+
+/// Whether `now` falls in the `start..end` window; windows with end < start wrap past midnight.
+fn in_window(now: u32, start: u32, end: u32) -> bool {
+    if start <= end {
+        now >= start && now < end
+    } else {
+        now >= start || now < end
+    }
+}
+
+fn unix_now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or(0.0)
+}
+
+/// Parses config value `key` as a number (or anything else FromStr), or None if it isn't
+/// set. A value that doesn't parse is recorded in `errors` and also gives None, so the
+/// caller's default applies.
+fn config_num<T: std::str::FromStr>(
+    configuration: &BTreeMap<String, String>,
+    key: &str,
+    errors: &mut Vec<String>,
+) -> Option<T> {
+    let value = configuration.get(key)?;
+    let parsed = value.trim().parse().ok();
+    if parsed.is_none() {
+        errors.push(format!("{}: can't parse {:?}, using the default", key, value));
+    }
+    parsed
+}
+
+/// Like config_num, for a duration: seconds, or a number with a unit as parse_duration
+/// takes, e.g. `30m`.
+fn config_secs(
+    configuration: &BTreeMap<String, String>,
+    key: &str,
+    errors: &mut Vec<String>,
+) -> Option<f64> {
+    let value = configuration.get(key)?;
+    let parsed = parse_duration(value);
+    if parsed.is_none() {
+        errors.push(format!("{}: can't parse {:?}, using the default", key, value));
+    }
+    parsed
+}
+
+/// Parses a duration into seconds: a plain number of seconds, or numbers with units
+/// `s`, `m`, `h` or `d`, run together like `1h30m`.
+fn parse_duration(value: &str) -> Option<f64> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse() {
+        return Some(secs);
+    }
+    let mut secs = 0.0;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let split = rest.find(|c: char| !c.is_ascii_digit() && c != '.')?;
+        let number: f64 = rest[..split].parse().ok()?;
+        let unit = match rest[split..].chars().next()? {
+            's' => 1.0,
+            'm' => 60.0,
+            'h' => 3600.0,
+            'd' => 86400.0,
+            _ => return None,
+        };
+        secs += number * unit;
+        rest = &rest[split + 1..];
+    }
+    (!value.is_empty()).then_some(secs)
+}
+
+/// Like config_num, for "true"/"false" (in any case).
+fn config_bool(
+    configuration: &BTreeMap<String, String>,
+    key: &str,
+    errors: &mut Vec<String>,
+) -> Option<bool> {
+    let value = configuration.get(key)?;
+    match value.trim().to_ascii_lowercase().as_str() {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => {
+            errors.push(format!(
+                "{}: expected true or false, got {:?}; using the default",
+                key, value
+            ));
+            None
+        }
+    }
+}
+
+/// Reads a comma-separated config value, trimming entries and dropping empty ones.
+fn config_list(configuration: &BTreeMap<String, String>, key: &str) -> Vec<String> {
+    configuration
+        .get(key)
+        .map(|s| {
+            s.split(',')
+                .map(|p| p.trim().to_string())
+                .filter(|p| !p.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Parses a color config value into SGR parameters: a color name (`red`, `bright-red`, ...),
+/// raw parameters like `41;97;1`, or a full escape sequence like `\x1b[41;97;1m`.
+fn parse_color(value: &str) -> Option<String> {
+    const NAMES: [&str; 8] = [
+        "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+    ];
+    let value = value.trim();
+    let sgr = value
+        .strip_prefix("\x1b[")
+        .and_then(|v| v.strip_suffix('m'))
+        .unwrap_or(value);
+    if !sgr.is_empty() && sgr.chars().all(|c| c.is_ascii_digit() || c == ';') {
+        return Some(sgr.to_string());
+    }
+    let name = value.to_ascii_lowercase();
+    let (base, name) = match name.strip_prefix("bright-") {
+        Some(name) => (90, name.to_string()),
+        None => (30, name),
+    };
+    let index = NAMES.iter().position(|n| *n == name)?;
+    Some((base + index).to_string())
+}
+
+/// The systemctl argument for a `systemd:<target>` suspend_action: a sleep or power verb
+/// like `suspend-then-hibernate`, or the `.target` unit of one to start. Anything else,
+/// such as `reboot.target` or `rescue.target`, which would take the box somewhere other
+/// than asleep or off, is None.
+fn systemd_target(action: &str) -> Option<&str> {
+    const VERBS: [&str; 6] = [
+        "suspend",
+        "hibernate",
+        "hybrid-sleep",
+        "suspend-then-hibernate",
+        "poweroff",
+        "halt",
+    ];
+    let target = action.strip_prefix("systemd:")?.trim();
+    let verb = target.strip_suffix(".target").unwrap_or(target);
+    VERBS.contains(&verb).then_some(target)
+}
+
+/// Replaces each `{name}` in `template` with its value from `vars`, in a single pass so
+/// values (e.g. process names) are never themselves expanded.
+fn fill_template(template: &str, vars: &[(&str, &str)]) -> String {
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        out.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        let value = after.find('}').and_then(|close| {
+            let (_, value) = vars.iter().find(|(name, _)| *name == &after[..close])?;
+            Some((value, close))
+        });
+        match value {
+            Some((value, close)) => {
+                out.push_str(value);
+                rest = &after[close + 1..];
+            }
+            None => {
+                out.push('{');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Formats seconds as `M:SS`, e.g. 287 -> `4:47`.
+fn format_mmss(secs: u64) -> String {
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+/// Formats seconds as whole minutes, rounded up, from a minute on (`5m`), else seconds (`45s`).
+fn format_short_duration(secs: f64) -> String {
+    if secs >= 60.0 {
+        format!("{}m", (secs / 60.0).ceil() as u64)
+    } else {
+        format!("{}s", secs as u64)
+    }
+}
+
+/// Formats seconds as `XmYYs`, e.g. 312 -> `5m12s`.
+fn format_minutes_secs(secs: u64) -> String {
+    format!("{}m{:02}s", secs / 60, secs % 60)
+}
+
+/// Picks the most common reason among active pane details and box signals, first seen
+/// winning ties. A detail's reason is its annotation if it has one (`claude(claude-working)`
+/// -> `claude-working`), the inner process for multiplexers (`tmux>cargo` -> `cargo`), or
+/// else the process or signal name itself, without any `×N` count.
+fn dominant_reason(details: &[String]) -> Option<String> {
+    let mut counts: Vec<(&str, usize)> = Vec::new();
+    for detail in details {
+        let detail = detail.rsplit('>').next().unwrap_or(detail);
+        let reason = match (detail.rfind('('), detail.strip_suffix(')')) {
+            (Some(open), Some(inner)) => &inner[open + 1..],
+            _ => detail.split('×').next().unwrap_or(detail),
+        };
+        match counts.iter_mut().find(|(r, _)| *r == reason) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((reason, 1)),
         }
-        // If total_panes == 0, keep current state (startup or no terminal panes yet)
+    }
+    // max_by_key keeps the last maximum, so search from the end to favor the first seen
+    counts
+        .into_iter()
+        .rev()
+        .max_by_key(|(_, count)| *count)
+        .map(|(reason, _)| reason.to_string())
+}
+
+/// Collapses repeated names into a single entry with a count (e.g. `cargo×3`),
+/// preserving first-seen order.
+fn collapse_duplicates(names: Vec<String>) -> Vec<String> {
+    let mut counts: Vec<(String, usize)> = Vec::new();
+    for name in names {
+        match counts.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((name, 1)),
+        }
+    }
+    counts
+        .into_iter()
+        .map(|(name, count)| {
+            if count > 1 {
+                format!("{}×{}", name, count)
+            } else {
+                name
+            }
+        })
+        .collect()
+}
+
+/// Truncates `s` to at most `max` chars, never splitting a multibyte character.
+/// Process names come from /proc/<pid>/comm and can be arbitrary UTF-8.
+fn truncate_chars(s: &str, max: usize) -> &str {
+    match s.char_indices().nth(max) {
+        Some((idx, _)) => &s[..idx],
+        None => s,
+    }
+}
+
+/// Counts terminal panes that still have a running process. Plugin panes (this one
+/// included) and exited panes held open have no shell for the idle check to find.
+fn count_terminal_panes(manifest: &PaneManifest) -> usize {
+    manifest
+        .panes
+        .values()
+        .flatten()
+        .filter(|pane| !pane.is_plugin && !pane.exited)
+        .count()
+}
+
+/// Truncates `msg` to `cols` chars and right-pads it with spaces to fill the bar.
+fn pad_to_width(msg: &str, cols: usize) -> String {
+    let truncated = truncate_chars(msg, cols);
+    let padding = cols.saturating_sub(truncated.chars().count());
+    format!("{}{}", truncated, " ".repeat(padding))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    thread_local! {
+        // (command line, context["command"]) for each command run
+        static COMMANDS: RefCell<Vec<(Vec<String>, String)>> = const { RefCell::new(Vec::new()) };
+    }
+
+    pub fn run_command(cmd: &[&str], context: BTreeMap<String, String>) {
+        let cmd = cmd.iter().map(|arg| arg.to_string()).collect();
+        let name = context.get("command").cloned().unwrap_or_default();
+        COMMANDS.with(|commands| commands.borrow_mut().push((cmd, name)));
+    }
+
+    pub fn set_timeout(_secs: f64) {}
+
+    /// The commands run since the last call.
+    fn take_commands() -> Vec<(Vec<String>, String)> {
+        COMMANDS.with(|commands| commands.borrow_mut().drain(..).collect())
+    }
+
+    /// A State as load leaves it, minus everything that needs the zellij host.
+    fn test_state() -> State {
+        State {
+            loaded: true,
+            zellij_pid: 1234,
+            poll_interval_secs: 5.0,
+            tick_interval_secs: 5.0,
+            ..Default::default()
+        }
+    }
+
+    /// One timer tick as far as idle accounting goes: the counters advance, then the
+    /// idle check's result comes back.
+    fn tick(state: &mut State, idle_check_output: &str) {
+        state.poll_count += 1;
+        state.clock_secs += state.tick_interval_secs;
+        state.update_idle_elapsed();
+        state.parse_idle_check_output(idle_check_output.as_bytes());
+    }
+
+    /// A timer tick through update, answering the idle check it starts with `dump`.
+    fn timer_tick(state: &mut State, dump: &str) {
+        // Fired right away, the timer would look superseded
+        state.next_tick_unix = 0.0;
+        state.update(Event::Timer(state.tick_interval_secs));
+        let context = BTreeMap::from([("command".to_string(), "idle_check".to_string())]);
+        if take_commands().iter().any(|(_, command)| command == "idle_check") {
+            state.update(Event::RunCommandResult(
+                Some(0),
+                dump.as_bytes().to_vec(),
+                Vec::new(),
+                context,
+            ));
+        }
+    }
+
+    /// The idle check's `$n`, after `bash -c <script> _`.
+    fn dump_arg(state: &State, n: usize) -> String {
+        state.idle_check_command()[3 + n].clone()
+    }
+
+    /// A timer tick through update, counting the suspend commands it starts.
+    fn suspends(state: &mut State) -> usize {
+        state.next_tick_unix = 0.0;
+        state.update(Event::Timer(state.tick_interval_secs));
+        take_commands()
+            .into_iter()
+            .filter(|(_, command)| command == "suspend")
+            .count()
+    }
+
+    #[test]
+    fn countdown_arms_counts_down_and_triggers_suspend() {
+        const IDLE_SHELL: &str = "stat:100:100 (bash) S 1234 100 100 34816 100 4194560\n";
+        let mut state = test_state();
+        state.idle_timeout_secs = 20.0;
+        state.countdown_secs = 10.0;
+        take_commands();
+
+        // Idle from the first check on; the countdown arms on the tick that sees 20s idle
+        timer_tick(&mut state, IDLE_SHELL);
+        assert!(state.is_idle);
+        for elapsed in [10.0, 15.0] {
+            timer_tick(&mut state, IDLE_SHELL);
+            assert_eq!(state.idle_elapsed_secs, elapsed);
+            assert!(!state.countdown_active);
+        }
+        timer_tick(&mut state, IDLE_SHELL);
+        assert_eq!(state.idle_elapsed_secs, 20.0);
+        assert!(state.countdown_active);
+        assert_eq!(state.countdown_remaining, 10.0);
+
+        timer_tick(&mut state, IDLE_SHELL);
+        assert_eq!(state.countdown_remaining, 5.0);
+        assert!(!state.suspend_triggered);
+
+        state.next_tick_unix = 0.0;
+        state.update(Event::Timer(5.0));
+        assert!(state.suspend_triggered);
+        assert!(!state.countdown_active);
+        let suspends: Vec<_> = take_commands()
+            .into_iter()
+            .filter(|(_, command)| command == "suspend")
+            .collect();
+        assert_eq!(suspends.len(), 1);
+        assert_eq!(suspends[0].0[4], "suspend");
+    }
+
+    #[test]
+    fn suspend_banner_hold_defers_the_suspend_and_input_cancels_it() {
+        let mut state = test_state();
+        state.suspend_banner_hold_secs = 5.0;
+        state.countdown_active = true;
+        state.countdown_remaining = 5.0;
+        take_commands();
+
+        assert_eq!(suspends(&mut state), 0);
+        assert!(state.suspend_triggered);
+        state.update(Event::InputReceived);
+        assert!(!state.suspend_triggered);
+        assert_eq!(suspends(&mut state), 0);
+
+        state.countdown_active = true;
+        state.countdown_remaining = 5.0;
+        assert_eq!(suspends(&mut state), 0);
+        assert_eq!(suspends(&mut state), 1);
+    }
+
+    #[test]
+    fn post_resume_command_runs_when_the_savings_file_says_suspended() {
+        let mut state = test_state();
+        state.post_resume_command = "ntpdate -s pool.ntp.org".to_string();
+        let savings_read = |state: &mut State, savings: &str| {
+            take_commands();
+            state.update(Event::RunCommandResult(
+                Some(0),
+                savings.as_bytes().to_vec(),
+                Vec::new(),
+                BTreeMap::from([("command".to_string(), "savings_read".to_string())]),
+            ));
+            take_commands()
+                .into_iter()
+                .filter(|(_, command)| command == "post_resume")
+                .map(|(cmdline, _)| cmdline[2].clone())
+                .collect::<Vec<_>>()
+        };
+
+        assert!(savings_read(&mut state, "suspends=3\nsuspended_at_unix=0\n").is_empty());
+        assert_eq!(
+            savings_read(&mut state, "suspends=3\nsuspended_at_unix=1000\nboot_unix=2000\n"),
+            ["ntpdate -s pool.ntp.org"]
+        );
+        assert_eq!(state.savings.suspended_at_unix, 0.0);
+    }
+
+    #[test]
+    fn dump_scripts_leave_out_their_own_bash() {
+        for script in [PROC_DUMP_SCRIPT, MACOS_PROC_DUMP_SCRIPT] {
+            assert!(script.contains(r#"[ "$child" = "$$" ] && continue"#));
+        }
+    }
+
+    #[test]
+    fn poll_jitter_spreads_ticks_and_the_clock_follows_them() {
+        let delays = |zellij_pid: u32| {
+            let mut state = State {
+                zellij_pid,
+                poll_jitter_secs: 2.0,
+                ..test_state()
+            };
+            (0..8)
+                .map(|_| {
+                    state.schedule_tick(5.0);
+                    state.tick_delay_secs
+                })
+                .collect::<Vec<_>>()
+        };
+        let box_a = delays(1234);
+        assert!(box_a.iter().all(|d| (3.0..=7.0).contains(d)));
+        assert!(box_a.iter().any(|d| *d != box_a[0]));
+        assert_eq!(delays(1234), box_a);
+        assert_ne!(delays(1235), box_a);
+
+        let mut state = State {
+            poll_jitter_secs: 2.0,
+            ..test_state()
+        };
+        state.schedule_tick(5.0);
+        let delay = state.tick_delay_secs;
+        state.next_tick_unix = 0.0;
+        state.update(Event::Timer(5.0));
+        assert_eq!(state.clock_secs, delay);
+        assert_eq!(state.tick_interval_secs, 5.0);
+
+        state.configure(&BTreeMap::from([("poll_jitter_secs".to_string(), "1m".to_string())]));
+        assert_eq!(state.poll_jitter_secs, DEFAULT_POLL_INTERVAL_SECS / 2.0);
+    }
+
+    #[test]
+    fn require_confirm_suspend_waits_for_the_confirm_pipe() {
+        fn confirm(state: &mut State) -> usize {
+            state.pipe(PipeMessage {
+                source: PipeSource::Keybind,
+                name: "zellij-idle-confirm".to_string(),
+                payload: None,
+                args: BTreeMap::new(),
+                is_private: false,
+            });
+            take_commands()
+                .into_iter()
+                .filter(|(_, command)| command == "suspend")
+                .count()
+        }
+        let mut state = test_state();
+        state.no_color = true;
+        state.require_confirm_suspend = true;
+        state.is_idle = true;
+        state.idle_timeout_secs = 5.0;
+        state.countdown_active = true;
+        state.countdown_remaining = 5.0;
+        take_commands();
+
+        assert_eq!(suspends(&mut state), 0);
+        assert!(state.awaiting_confirm);
+        assert_eq!(state.status_line(16), "CONFIRM SUSPEND ");
+        // Still idle, but no new countdown while it waits
+        assert_eq!(suspends(&mut state), 0);
+        assert!(state.awaiting_confirm && !state.countdown_active);
+
+        // The confirm keybinding is input too, and doesn't cancel
+        state.update(Event::InputReceived);
+        assert_eq!(confirm(&mut state), 1);
+        assert!(!state.awaiting_confirm);
+
+        // Other input cancels on the next tick
+        state.suspend_triggered = false;
+        state.suspend_command_sent = false;
+        state.awaiting_confirm = true;
+        state.confirm_input_unix = Some(unix_now() - CONFIRM_GRACE_SECS);
+        assert_eq!(suspends(&mut state), 0);
+        assert!(!state.awaiting_confirm);
+        assert_eq!(confirm(&mut state), 0);
+
+        // suspend-now doesn't wait for a confirm
+        state.awaiting_confirm = true;
+        state.confirm_input_unix = Some(unix_now());
+        state.pipe(PipeMessage {
+            source: PipeSource::Keybind,
+            name: "zellij-idle-suspend-now".to_string(),
+            payload: None,
+            args: BTreeMap::new(),
+            is_private: false,
+        });
+        assert!(!state.awaiting_confirm);
+        assert_eq!(state.confirm_input_unix, None);
+        assert!(take_commands().iter().any(|(_, command)| command == "suspend"));
+    }
+
+    #[test]
+    fn warn_first_cycle_only_warns_the_first_time() {
+        const IDLE_SHELL: &str = "stat:100:100 (bash) S 1234 100 100 34816 100 4194560\n";
+        let mut state = test_state();
+        state.no_color = true;
+        state.warn_first_cycle = true;
+        state.idle_timeout_secs = 10.0;
+        state.countdown_secs = 5.0;
+        take_commands();
+        for _ in 0..2 {
+            timer_tick(&mut state, IDLE_SHELL);
+        }
+        assert!(state.countdown_active);
+        assert_eq!(state.status_line(16), "WOULD SUSPEND 5s");
+
+        timer_tick(&mut state, IDLE_SHELL);
+        assert!(state.first_cycle_warned);
+        assert!(!state.suspend_triggered);
+        assert!(!state.countdown_active);
+        assert!(!take_commands().iter().any(|(_, command)| command == "suspend"));
+
+        // The next idle cycle takes a full idle timeout again
+        timer_tick(&mut state, IDLE_SHELL);
+        assert!(!state.countdown_active);
+        timer_tick(&mut state, IDLE_SHELL);
+        assert_eq!(state.status_line(16), "SUSPEND 5s      ");
+        timer_tick(&mut state, IDLE_SHELL);
+        assert!(state.suspend_triggered);
+    }
+
+    #[test]
+    fn input_mid_countdown_cancels_it() {
+        const IDLE_SHELL: &str = "stat:100:100 (bash) S 1234 100 100 34816 100 4194560\n";
+        let mut state = test_state();
+        state.idle_timeout_secs = 10.0;
+        state.countdown_secs = 10.0;
+        for _ in 0..3 {
+            timer_tick(&mut state, IDLE_SHELL);
+        }
+        assert!(state.countdown_active);
+
+        state.update(Event::InputReceived);
+        assert!(!state.countdown_active);
+        assert_eq!(state.countdown_remaining, 0.0);
+        assert!(!state.is_idle);
+        assert_eq!(state.idle_elapsed_secs, 0.0);
+
+        // The countdown starts over only after a full idle timeout
+        timer_tick(&mut state, IDLE_SHELL);
+        assert_eq!(state.idle_elapsed_secs, 5.0);
+        assert!(!state.countdown_active);
+        timer_tick(&mut state, IDLE_SHELL);
+        assert!(state.countdown_active);
+        assert_eq!(state.countdown_remaining, 10.0);
+        assert!(!take_commands().iter().any(|(_, command)| command == "suspend"));
+    }
+
+    #[test]
+    fn heartbeat_is_written_after_each_successful_idle_check() {
+        let mut state = test_state();
+        state.heartbeat_file = "/run/zellij-idle.heartbeat".to_string();
+        take_commands();
+        state.poll_count = 7;
+        state.handle_idle_check_result(Some(0), b"stat:100:100 (bash) S 1234 100 100 34816 100 0\n", b"");
+        let heartbeats: Vec<_> = take_commands()
+            .into_iter()
+            .filter(|(_, command)| command == "heartbeat")
+            .collect();
+        assert_eq!(heartbeats.len(), 1);
+        assert_eq!(heartbeats[0].0[4], "/run/zellij-idle.heartbeat");
+        let contents = &heartbeats[0].0[5];
+        assert!(contents.starts_with("unix="));
+        assert!(contents.ends_with("\npoll=7\nstate=idle\n"));
+
+        state.handle_idle_check_result(Some(1), b"", b"");
+        assert!(!take_commands().iter().any(|(_, command)| command == "heartbeat"));
+    }
+
+    #[test]
+    fn idle_elapsed_counts_idle_polls() {
+        let mut state = State {
+            poll_interval_secs: 5.0,
+            tick_interval_secs: 5.0,
+            ..Default::default()
+        };
+        tick(&mut state, "active:10:cargo\n");
+        assert!(!state.is_idle);
+        assert_eq!(state.idle_elapsed_secs, 0.0);
+
+        for n in 1..=20 {
+            tick(&mut state, "idle:10:bash\n");
+            assert!(state.is_idle);
+            assert_eq!(state.idle_elapsed_secs, n as f64 * 5.0);
+        }
+    }
+
+    #[test]
+    fn json_log_lines_carry_event_and_counters() {
+        let mut state = State {
+            poll_interval_secs: 5.0,
+            tick_interval_secs: 5.0,
+            log_json: true,
+            ..Default::default()
+        };
+        tick(&mut state, "idle:10:bash\n");
+        let lines: Vec<serde_json::Value> = state
+            .log_buffer
+            .iter()
+            .map(|line| serde_json::from_str(line).expect("log line is JSON"))
+            .collect();
+        assert_eq!(lines[0]["event"], "poll");
+        assert_eq!(lines[0]["poll"], 1);
+        assert_eq!(lines[0]["total_panes"], 1);
+        assert_eq!(lines[1]["event"], "idle");
+    }
+
+    #[test]
+    fn terminal_pane_count_skips_plugins_and_exited_panes() {
+        let pane = |is_plugin, exited, is_floating| PaneInfo {
+            is_plugin,
+            exited,
+            is_floating,
+            ..Default::default()
+        };
+        let mut manifest = PaneManifest::default();
+        manifest.panes.insert(
+            0,
+            vec![pane(false, false, false), pane(true, false, false), pane(false, false, true)],
+        );
+        manifest.panes.insert(1, vec![pane(false, true, false), pane(false, false, false)]);
+        assert_eq!(count_terminal_panes(&manifest), 3);
+    }
+
+    #[test]
+    fn poll_interval_backs_off_while_idle() {
+        let mut state = State {
+            poll_interval_secs: 5.0,
+            idle_timeout_secs: 1000.0,
+            max_poll_interval_secs: 60.0,
+            poll_backoff_after_secs: 100.0,
+            ..Default::default()
+        };
+        let mut intervals = Vec::new();
+        while state.idle_elapsed_secs < 1000.0 {
+            state.tick_interval_secs = state.next_poll_interval();
+            intervals.push(state.tick_interval_secs);
+            tick(&mut state, "idle:10:bash\n");
+        }
+        // Fast for the first 100s idle, then doubling up to the cap, and cut short at
+        // the end so the countdown starts right at the timeout
+        let mut expected = vec![5.0; 20];
+        expected.extend([10.0, 20.0, 40.0]);
+        expected.extend([60.0; 13]);
+        expected.push(50.0);
+        assert_eq!(intervals, expected);
+        assert_eq!(state.idle_elapsed_secs, 1000.0);
+        assert_eq!(state.next_poll_interval(), 5.0);
+    }
+
+    #[test]
+    fn suspend_that_never_reports_back_times_out() {
+        let mut state = test_state();
+        state.suspend_timeout_secs = 10.0;
+        state.suspend_triggered = true;
+        state.suspend_command_sent = true;
+        take_commands();
+        state.run_suspend_command();
+        let (_, command) = &take_commands()[0];
+        assert_eq!(command, "suspend");
+        assert_eq!(state.suspend_started_secs, Some(0.0));
+
+        timer_tick(&mut state, "");
+        assert!(state.suspend_started_secs.is_some());
+        timer_tick(&mut state, "");
+        assert_eq!(state.suspend_started_secs, None);
+        assert!(state.suspend_retry_at_unix.is_some());
+
+        // The hung command finishing later changes nothing
+        state.update(Event::RunCommandResult(
+            Some(1),
+            Vec::new(),
+            Vec::new(),
+            BTreeMap::from([
+                ("command".to_string(), "suspend".to_string()),
+                ("run".to_string(), "1".to_string()),
+            ]),
+        ));
+        assert!(state.suspend_retry_at_unix.is_some());
+        assert_eq!(state.suspend_attempts, 1);
+    }
+
+    #[test]
+    fn failed_suspend_rearms_after_next_idle_cycle() {
+        let mut state = State {
+            poll_interval_secs: 5.0,
+            tick_interval_secs: 5.0,
+            suspend_triggered: true,
+            suspend_command_sent: true,
+            suspend_attempts: 4,
+            suspend_max_retries: 3,
+            ..Default::default()
+        };
+        tick(&mut state, "idle:10:bash\n");
+        state.update(Event::RunCommandResult(
+            Some(1),
+            Vec::new(),
+            b"not authenticated".to_vec(),
+            BTreeMap::from([("command".to_string(), "suspend".to_string())]),
+        ));
+        assert!(!state.suspend_triggered);
+        assert!(!state.suspend_command_sent);
+        assert_eq!(state.suspend_attempts, 0);
+        assert_eq!(state.suspend_retry_at_unix, None);
+        assert_eq!(state.idle_elapsed_secs, 0.0);
+        assert!(state.is_idle);
+    }
+
+    #[test]
+    fn narrow_status_line_shows_a_state_glyph() {
+        let mut state = test_state();
+        state.no_color = true;
+        state.active_processes = vec!["cargo".into()];
+        let widths = |state: &State| (0..4).map(|cols| state.status_line(cols)).collect::<Vec<_>>();
+        assert_eq!(widths(&state), ["", "A", "A ", "A  "]);
+        assert_eq!(state.status_line(4), "car+");
+
+        state.is_idle = true;
+        assert_eq!(widths(&state), ["", "I", "I ", "I  "]);
+        state.countdown_active = true;
+        state.countdown_remaining = 30.0;
+        assert_eq!(widths(&state), ["", "C", "C ", "C  "]);
+        state.enabled = false;
+        assert_eq!(widths(&state), ["", "-", "- ", "-  "]);
+
+        state.no_color = false;
+        state.enabled = true;
+        assert_eq!(state.status_line(0), "");
+        assert_eq!(state.status_line(1), format!("\x1b[{}mC\x1b[0m", state.color_countdown));
+        state.loaded = false;
+        assert_eq!(widths(&state), ["", "…", "… ", "…  "]);
+    }
+
+    #[test]
+    fn status_line_truncates_multibyte_process_names() {
+        let state = State {
+            loaded: true,
+            zellij_pid: 1234,
+            no_color: true,
+            active_processes: vec!["café-server".into()],
+            ..Default::default()
+        };
+        assert_eq!(state.status_line(5), "café+");
+        assert_eq!(state.status_line(4), "caf+");
+        assert_eq!(state.status_line(14), "café-server   ");
+    }
+
+    #[test]
+    fn sparkline_follows_the_state_text_when_it_fits() {
+        let mut state = test_state();
+        state.no_color = true;
+        state.show_sparkline = true;
+        tick(&mut state, "idle:10:bash\n");
+        tick(&mut state, "active:10:cargo\n");
+        tick(&mut state, "idle:10:bash\n");
+        assert_eq!(state.sparkline(), "▁█▁");
+        assert_eq!(state.status_line(20), "IDLE 5s          ▁█▁");
+        tick(&mut state, "active:10:cargo-build-everything\n");
+        assert_eq!(state.status_line(20), "cargo-build-ev+ ▁█▁█");
+        // Too narrow to spare the room
+        assert_eq!(state.status_line(15), "cargo-build-ev+");
+
+        for _ in 0..SPARKLINE_POLLS {
+            tick(&mut state, "idle:10:bash\n");
+        }
+        assert_eq!(state.sparkline(), "▁".repeat(SPARKLINE_POLLS));
+    }
+
+    #[test]
+    fn arming_progress_counts_down_to_the_countdown() {
+        let mut state = test_state();
+        state.configure(&BTreeMap::from([(
+            "show_arming_progress".to_string(),
+            "true".to_string(),
+        )]));
+        state.no_color = true;
+        state.idle_timeout_secs = 300.0;
+        tick(&mut state, "active:10:cargo\n");
+        for _ in 0..9 {
+            tick(&mut state, "idle:10:bash\n");
+        }
+        assert_eq!(state.status_line(12), "IDLE→4m15s  ");
+        state.idle_elapsed_secs = 290.0;
+        assert_eq!(state.status_line(12), "IDLE→10s    ");
+    }
+
+    #[test]
+    fn countdown_banner_inverts_within_bell_at_secs() {
+        let mut state = test_state();
+        state.bell_at_secs = 5.0;
+        state.countdown_secs = 60.0;
+        state.start_countdown();
+        assert!(!state.status_line(20).contains(";7m"));
+        state.countdown_remaining = 5.0;
+        assert!(state.status_line(20).starts_with(&format!("\x1b[{};7m", state.color_countdown)));
+    }
+
+    #[test]
+    fn detached_idle_timeout_applies_with_no_clients() {
+        let mut state = test_state();
+        state.idle_timeout_secs = 600.0;
+        state.detached_idle_timeout_secs = 60.0;
+        state.update_timeout_profile();
+        assert_eq!(state.idle_timeout(), 600.0);
+        assert_eq!(state.timeout_profile, None);
+
+        state.connected_clients = Some(0);
+        state.update_timeout_profile();
+        assert_eq!(state.idle_timeout(), 60.0);
+        assert_eq!(state.timeout_profile.as_deref(), Some("detached"));
+        assert_eq!(
+            state.log_buffer.last().map(String::as_str),
+            Some("timeout profile: detached (idle timeout 60s)")
+        );
+
+        state.connected_clients = Some(1);
+        state.update_timeout_profile();
+        assert_eq!(state.idle_timeout(), 600.0);
+        assert_eq!(state.timeout_profile, None);
+    }
+
+    #[test]
+    fn battery_only_suppresses_on_ac_and_uses_the_battery_timeout() {
+        let mut state = test_state();
+        state.idle_timeout_secs = 600.0;
+        state.battery_only = true;
+        state.battery_timeout_secs = 120.0;
+        assert_eq!(state.suspend_suppressed_reason(), Some("on AC"));
+
+        state.update_power_source("power:-:unknown\n");
+        assert_eq!(state.suspend_suppressed_reason(), Some("on AC"));
+        assert_eq!(state.idle_timeout(), 600.0);
+
+        state.update_power_source("stat:1:x\npower:-:battery\n");
+        assert_eq!(state.suspend_suppressed_reason(), None);
+        assert_eq!(state.idle_timeout(), 120.0);
+    }
+
+    #[test]
+    fn selfcheck_report_adds_the_idle_scan() {
+        let state = test_state();
+        let output = "ok: gcloud is /usr/bin/gcloud\nFAIL: gce metadata server unreachable\n---\n\
+stat:100:100 (bash) S 1 100 100 34816 100 0
+sshsessions:-:1
+";
+        assert_eq!(
+            state.selfcheck_report(output),
+            [
+                "ok: gcloud is /usr/bin/gcloud",
+                "FAIL: gce metadata server unreachable",
+                "ok: idle scan: idle:100:bash",
+            ]
+        );
+        assert_eq!(
+            state.selfcheck_report("skip: no suspend CLI to check\n---\n").last().unwrap(),
+            "FAIL: idle scan found no pane shells"
+        );
+    }
+
+    #[test]
+    fn denied_permissions_show_no_perms() {
+        let state = State {
+            permission_denied: true,
+            no_color: true,
+            ..Default::default()
+        };
+        assert_eq!(state.status_line(10), "NO PERMS  ");
+    }
+
+    #[test]
+    fn lock_action_has_its_own_banner() {
+        let mut state = test_state();
+        state.no_color = true;
+        state.countdown_active = true;
+        state.countdown_remaining = 30.0;
+        state.suspend_action = "lock".to_string();
+        assert_eq!(state.status_line(10), "LOCK 30s  ");
+        state.suspend_triggered = true;
+        assert_eq!(state.status_line(10), "LOCK!     ");
+
+        // Also as a later step of a chain, once the chain gets to it
+        state.suspend_triggered = false;
+        state.suspend_chain = vec!["suspend".to_string(), "lock".to_string()];
+        state.suspend_action = "suspend".to_string();
+        assert_eq!(state.status_line(12), "SUSPEND 30s ");
+        state.suspend_action = "lock".to_string();
+        assert_eq!(state.status_line(12), "LOCK 30s    ");
+
+        // A template that's set wins
+        let config =
+            BTreeMap::from([("status_format_countdown".to_string(), "BYE {remaining}".to_string())]);
+        state.status_formats = StatusFormats::from_config(&config);
+        assert_eq!(state.status_line(12), "BYE 30s     ");
+    }
+
+    #[test]
+    fn none_at_the_end_of_a_chain_starts_a_new_idle_cycle() {
+        let mut state = test_state();
+        state.suspend_chain = vec!["systemd-suspend".to_string(), "none".to_string()];
+        state.suspend_chain_index = 1;
+        state.suspend_action = "none".to_string();
+        state.suspend_triggered = true;
+        state.suspend_command_sent = true;
+        take_commands();
+        state.run_suspend_command();
+        assert!(take_commands().is_empty());
+        assert!(!state.suspend_triggered && !state.suspend_command_sent);
+        assert_eq!(state.suspend_action, "systemd-suspend");
+    }
+
+    #[test]
+    fn extend_adds_to_the_countdown_or_the_idle_timeout() {
+        let mut state = test_state();
+        state.no_color = true;
+        state.clock_secs = 100.0;
+        state.last_activity_secs = 40.0;
+        state.extend(300.0);
+        assert_eq!(state.last_activity_secs, 340.0);
+        assert_eq!(state.idle_elapsed_secs, 0.0);
+        // A second extension is capped at `secs` from now
+        state.extend(300.0);
+        assert_eq!(state.last_activity_secs, 400.0);
+        assert!(state.status_line(20).starts_with("+5m "));
+
+        state.countdown_secs = 60.0;
+        state.start_countdown();
+        state.countdown_remaining = 10.0;
+        state.extend(30.0);
+        assert_eq!(state.countdown_remaining, 40.0);
+
+        state.suspend_triggered = true;
+        assert!(state.extend(30.0).starts_with("too late"));
+        assert_eq!(state.countdown_remaining, 40.0);
+    }
+
+    #[test]
+    fn keypress_cancels_pending_suspend_retry_without_resetting_idle() {
+        let mut state = State {
+            poll_interval_secs: 5.0,
+            tick_interval_secs: 5.0,
+            keypress_resets_idle: false,
+            suspend_triggered: true,
+            suspend_command_sent: true,
+            suspend_attempts: 1,
+            suspend_retry_at_unix: Some(unix_now() + 30.0),
+            ..Default::default()
+        };
+        tick(&mut state, "idle:10:bash\n");
+        state.update(Event::InputReceived);
+        assert!(!state.suspend_triggered);
+        assert!(!state.suspend_command_sent);
+        assert_eq!(state.suspend_retry_at_unix, None);
+        assert!(state.is_idle);
+        assert_eq!(state.idle_elapsed_secs, 5.0);
+    }
+
+    #[test]
+    fn input_debounce_drops_input_without_a_quiet_spell_before_it() {
+        let mut state = test_state();
+        state.input_debounce_secs = 2.0;
+        tick(&mut state, "idle:10:bash\n");
+        tick(&mut state, "idle:10:bash\n");
+        assert!(state.is_idle);
+
+        // A trickle of terminal reports: each is too close to the one before
+        state.last_accepted_input_unix = unix_now() - 1.0;
+        assert!(!state.handle_input());
+        assert!(state.is_idle);
+        state.last_accepted_input_unix = unix_now() - 1.5;
+        state.update(Event::InputReceived);
+        assert!(state.is_idle);
+        assert_eq!(state.input_noise_events, 2);
+
+        state.last_accepted_input_unix = unix_now() - 3.0;
+        assert!(state.handle_input());
+        assert!(!state.is_idle);
+        assert_eq!(state.last_input_unix, state.last_accepted_input_unix);
+        assert_eq!(state.input_noise_events, 2);
+    }
+
+    #[test]
+    fn input_debounce_never_swallows_input_during_a_countdown() {
+        let mut state = test_state();
+        state.input_debounce_secs = 2.0;
+        state.keypress_resets_idle = false;
+        state.countdown_active = true;
+        state.countdown_remaining = 10.0;
+
+        // Input keeps arriving inside the window, and each one is still heard
+        state.last_accepted_input_unix = unix_now() - 0.5;
+        assert!(state.handle_input());
+        assert!(!state.countdown_active);
+        state.countdown_active = true;
+        state.countdown_remaining = 10.0;
+        assert!(state.handle_input());
+        assert!(!state.countdown_active);
+        assert_eq!(state.input_noise_events, 0);
+    }
+
+    #[test]
+    fn suspend_command_runs_through_sudo_and_env() {
+        let mut state = State::default();
+        assert!(state.suspend_command_prefix().is_empty());
+
+        state.configure(&BTreeMap::from([
+            ("suspend_run_as".to_string(), "root".to_string()),
+            ("suspend_env".to_string(), "CLOUDSDK_CONFIG=/etc/gcloud, junk".to_string()),
+        ]));
+        assert_eq!(state.suspend_command_prefix(), ["sudo", "-n", "env", "CLOUDSDK_CONFIG=/etc/gcloud"]);
+        assert_eq!(state.config_errors, [r#"suspend_env: ignoring "junk"; expected KEY=VALUE"#]);
+
+        state.suspend_run_as = "ops".to_string();
+        state.suspend_env.clear();
+        assert_eq!(state.suspend_command_prefix(), ["sudo", "-n", "-u", "ops"]);
+    }
+
+    #[test]
+    fn fallback_action_replaces_a_suspend_the_box_cant_do() {
+        let mut state = test_state();
+        state.configure(&BTreeMap::from([(
+            "fallback_action".to_string(),
+            "command:tmux-notify idle".to_string(),
+        )]));
+        assert!(state.config_errors.is_empty());
+        assert_eq!(state.suspend_unavailable_reason(), None);
+
+        state.cli_missing = true;
+        assert_eq!(state.suspend_unavailable_reason().as_deref(), Some("gcloud CLI not found"));
+        state.suspend_triggered = true;
+        take_commands();
+        state.run_suspend_command();
+        assert_eq!(
+            take_commands(),
+            [(vec!["bash".to_string(), "-c".to_string(), "tmux-notify idle".to_string()], "fallback".to_string())]
+        );
+        assert_eq!(state.suspend_attempts, 0);
+
+        // A later suspend_action in the chain gets its turn first
+        state.suspend_chain = vec!["suspend".to_string(), "systemd-suspend".to_string()];
+        assert_eq!(state.suspend_unavailable_reason(), None);
+
+        state.configure(&BTreeMap::from([
+            ("suspend_action".to_string(), "auto".to_string()),
+            ("fallback_action".to_string(), "sleep".to_string()),
+        ]));
+        assert_eq!(state.fallback_action, "log");
+        assert!(state.config_errors[0].starts_with(r#"fallback_action: unknown "sleep""#));
+        state.cli_missing = false;
+        state.detected_provider = Some("none".to_string());
+        state.suspend_triggered = true;
+        state.run_suspend_command();
+        assert!(take_commands().is_empty());
+        assert!(!state.suspend_triggered);
+    }
+
+    #[test]
+    fn unknown_suspend_action_is_refused() {
+        let mut state = test_state();
+        let config = BTreeMap::from([("suspend_action".to_string(), "suspend,stopp".to_string())]);
+        state.configure(&config);
+        state.no_color = true;
+        assert_eq!(state.bad_suspend_action.as_deref(), Some("stopp"));
+        assert!(state.config_errors[0].starts_with(r#"suspend_action: unknown "stopp""#));
+        assert_eq!(state.status_line(12), "BAD ACTION  ");
+
+        state.suspend_triggered = true;
+        take_commands();
+        state.run_suspend_command();
+        assert!(take_commands().is_empty());
+        assert!(!state.suspend_triggered);
+
+        // A custom suspend_command is free to take any action name
+        let mut config = config;
+        config.insert("suspend_command".to_string(), "my-suspend \"$1\"".to_string());
+        state.configure(&config);
+        assert_eq!(state.bad_suspend_action, None);
+    }
+
+    #[test]
+    fn reload_replaces_the_whole_config_and_keeps_counters() {
+        let mut state = test_state();
+        state.config = BTreeMap::from([
+            ("idle_timeout_secs".to_string(), "60".to_string()),
+            ("enabled".to_string(), "false".to_string()),
+        ]);
+        let config = state.config.clone();
+        state.configure(&config);
+        state.poll_count = 7;
+        assert!(!state.enabled);
+
+        let reply = state.reload_config("# tighter\nidle_timeout_secs=120\n\nenabled=false\nnoise\n");
+        assert_eq!(reply, "reloaded: idle_timeout_secs");
+        assert_eq!(state.idle_timeout_secs, 120.0);
+        assert_eq!(state.poll_count, 7);
+
+        // Keys left out go back to their defaults
+        assert_eq!(state.reload_config(""), "reloaded: enabled, idle_timeout_secs");
+        assert!(state.enabled);
+        assert_eq!(state.idle_timeout_secs, DEFAULT_IDLE_TIMEOUT_SECS);
+        assert_eq!(state.poll_count, 7);
+
+        // A file is read by its absolute or ~/ path; anything else leaves the config be
+        let reload = |state: &mut State, payload: &str| {
+            take_commands();
+            state.pipe(PipeMessage {
+                source: PipeSource::Keybind,
+                name: "zellij-idle-reload".to_string(),
+                payload: Some(payload.to_string()),
+                args: BTreeMap::new(),
+                is_private: false,
+            });
+            take_commands()
+                .into_iter()
+                .filter(|(_, command)| command == "reload")
+                .map(|(cmdline, _)| cmdline[4].clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(reload(&mut state, "~/zellij-idle.conf\n"), ["~/zellij-idle.conf"]);
+        assert_eq!(reload(&mut state, "/etc/zellij-idle.conf"), ["/etc/zellij-idle.conf"]);
+        state.config.insert("enabled".to_string(), "false".to_string());
+        assert!(reload(&mut state, "zellij-idle.conf").is_empty());
+        assert_eq!(state.config.get("enabled").map(String::as_str), Some("false"));
+        assert!(reload(&mut state, "# note").is_empty());
+        assert!(state.config.is_empty());
+    }
+
+    #[test]
+    fn systemd_actions_pass_checked_targets_to_systemctl() {
+        let mut state = test_state();
+        for (action, bad) in [
+            ("systemd:suspend-then-hibernate", false),
+            ("systemd:hibernate.target", false),
+            ("systemd:sleep-deep@1.target", true),
+            ("systemd:reboot", true),
+            ("systemd:reboot.target", true),
+            ("systemd:rescue.target", true),
+            ("systemd:emergency.target", true),
+            ("systemd:-H evil.target", true),
+            ("systemd:a;b.target", true),
+            ("systemd:.target", true),
+        ] {
+            let config = BTreeMap::from([("suspend_action".to_string(), action.to_string())]);
+            state.configure(&config);
+            assert_eq!(state.bad_suspend_action.is_some(), bad, "{}", action);
+        }
+
+        let config = BTreeMap::from([(
+            "suspend_action".to_string(),
+            "systemd:suspend-then-hibernate".to_string(),
+        )]);
+        state.configure(&config);
+        assert_eq!(state.suspend_cli(), "systemctl");
+        state.suspend_triggered = true;
+        take_commands();
+        state.run_suspend_command();
+        let commands = take_commands();
+        assert_eq!(commands[0].1, "suspend");
+        assert_eq!(commands[0].0[2], LOCAL_SUSPEND_SCRIPT);
+        assert_eq!(commands[0].0[4..], ["suspend-then-hibernate", "systemctl"]);
+    }
+
+    #[test]
+    fn giving_up_on_suspend_shows_until_input() {
+        let mut state = test_state();
+        state.no_color = true;
+        state.suspend_triggered = true;
+        state.suspend_max_retries = 1;
+        state.suspend_attempts = 2;
+        state.suspend_attempt_failed();
+        assert_eq!(state.suspend_disabled_reason.as_deref(), Some("gave up after 2 attempts"));
+        assert_eq!(state.status_line(16), "SUSPEND FAILED  ");
+
+        // The next idle cycle's countdown still shows
+        state.countdown_active = true;
+        assert!(!state.status_line(16).starts_with("SUSPEND FAILED"));
+        state.countdown_active = false;
+
+        state.update(Event::InputReceived);
+        assert_eq!(state.suspend_disabled_reason, None);
+        assert!(!state.status_line(16).starts_with("SUSPEND FAILED"));
+    }
+
+    #[test]
+    fn suspend_action_list_is_a_fallback_chain() {
+        let mut state = State::default();
+        state.configure(&BTreeMap::new());
+        assert_eq!(state.suspend_chain, ["suspend"]);
+
+        let config = BTreeMap::from([(
+            "suspend_action".to_string(),
+            "gce-suspend, gce-stop,systemd-suspend".to_string(),
+        )]);
+        state.configure(&config);
+        assert_eq!(state.suspend_chain, ["suspend", "stop", "systemd-suspend"]);
+        assert_eq!(state.suspend_action, "suspend");
+
+        state.suspend_chain_index = 2;
+        state.suspend_action = "systemd-suspend".to_string();
+        state.cancel_suspend();
+        assert_eq!(state.suspend_chain_index, 0);
+        assert_eq!(state.suspend_action, "suspend");
+    }
+
+    #[test]
+    fn bad_config_values_are_reported_and_defaulted() {
+        let mut state = State::default();
+        let config = BTreeMap::from([
+            ("idle_timeout_secs".to_string(), "5min".to_string()),
+            ("dry_run".to_string(), "yes".to_string()),
+            ("countdown_secs".to_string(), " 45 ".to_string()),
+        ]);
+        state.configure(&config);
+        assert_eq!(state.idle_timeout_secs, DEFAULT_IDLE_TIMEOUT_SECS);
+        assert!(!state.dry_run);
+        assert_eq!(state.countdown_secs, 45.0);
+        assert_eq!(
+            state.config_errors,
+            [
+                r#"idle_timeout_secs: can't parse "5min", using the default"#,
+                r#"dry_run: expected true or false, got "yes"; using the default"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn durations_take_units() {
+        for (value, secs) in [
+            ("90", Some(90.0)),
+            ("90s", Some(90.0)),
+            ("30m", Some(1800.0)),
+            ("1h30m", Some(5400.0)),
+            ("1.5h", Some(5400.0)),
+            ("2d", Some(172800.0)),
+            ("5min", None),
+            ("1h30", None),
+            ("m", None),
+            ("", None),
+        ] {
+            assert_eq!(parse_duration(value), secs, "{:?}", value);
+        }
+
+        let mut state = State::default();
+        let config = BTreeMap::from([
+            ("idle_timeout_secs".to_string(), "30m".to_string()),
+            ("countdown_secs".to_string(), "2m".to_string()),
+            ("countdown_warnings".to_string(), "1m,30s".to_string()),
+        ]);
+        state.configure(&config);
+        assert_eq!(state.idle_timeout_secs, 1800.0);
+        assert_eq!(state.countdown_secs, 120.0);
+        assert_eq!(state.countdown_warnings, [60.0, 30.0]);
+        assert!(state.config_errors.is_empty());
+
+        // A bad warning is reported rather than quietly lost
+        let config =
+            BTreeMap::from([("countdown_warnings".to_string(), "1m,1x,30s".to_string())]);
+        state.configure(&config);
+        assert_eq!(state.countdown_warnings, [60.0, 30.0]);
+        assert_eq!(state.config_errors.len(), 1);
+        assert!(state.config_errors[0].contains("\"1x\""));
+
+        // So do the pipes' payloads
+        state.pipe(PipeMessage {
+            source: PipeSource::Keybind,
+            name: "zellij-idle-snooze".to_string(),
+            payload: Some("10m".to_string()),
+            args: BTreeMap::new(),
+            is_private: false,
+        });
+        assert_eq!(state.snooze_until_secs, state.clock_secs + 600.0);
+    }
+
+    #[test]
+    fn parse_mixed_panes() {
+        let mut state = test_state();
+        tick(&mut state, "active:10:cargo\nidle:11:bash\nactive:12:cargo\n");
+        assert!(!state.is_idle);
+        assert_eq!(state.active_pane_count, 2);
+        assert_eq!(state.shell_count, 3);
+        assert_eq!(state.active_processes, ["cargo×2"]);
+        assert_eq!(state.last_activity_secs, state.clock_secs);
+    }
+
+    #[test]
+    fn parse_all_idle_goes_idle_and_keeps_the_activity_baseline() {
+        let mut state = test_state();
+        tick(&mut state, "active:10:cargo\n");
+        let baseline = state.last_activity_secs;
+        tick(&mut state, "idle:10:bash\nidle:11:vim(ignored)\n");
+        assert!(state.is_idle);
+        assert_eq!(state.active_pane_count, 0);
+        assert!(state.active_processes.is_empty());
+        assert_eq!(state.last_activity_secs, baseline);
+    }
+
+    #[test]
+    fn parse_unknown_foregrounds_count_but_are_not_listed() {
+        let mut state = test_state();
+        tick(&mut state, "active:10:unknown\nactive:11:\n");
+        assert!(!state.is_idle);
+        assert_eq!(state.active_pane_count, 2);
+        assert!(state.active_processes.is_empty());
+    }
+
+    #[test]
+    fn parse_empty_output_holds_the_current_state() {
+        let mut state = test_state();
+        tick(&mut state, "idle:10:bash\n");
+        assert!(state.is_idle);
+        tick(&mut state, "");
+        assert!(state.is_idle);
+        assert_eq!(state.shell_count, 0);
+
+        let mut state = test_state();
+        tick(&mut state, "active:10:cargo\n");
+        tick(&mut state, "\n\n");
+        assert!(!state.is_idle);
+        assert_eq!(state.empty_poll_streak, 1);
+    }
+
+    #[test]
+    fn parse_idle_ratio_lets_a_few_busy_panes_go_idle() {
+        let output = "active:10:htop\nidle:11:bash\nidle:12:bash\nidle:13:bash\n";
+        let mut state = test_state();
+        tick(&mut state, output);
+        assert!(!state.is_idle);
+
+        let mut state = test_state();
+        state.idle_ratio_threshold = 0.75;
+        tick(&mut state, output);
+        assert!(state.is_idle);
+        assert_eq!(state.active_pane_count, 1);
+
+        // Box-wide signals still keep it awake
+        tick(&mut state, &format!("{}active:-:ssh-session\n", output));
+        assert!(!state.is_idle);
+    }
+
+    #[test]
+    fn failed_idle_check_keeps_the_previous_state() {
+        let mut state = test_state();
+        tick(&mut state, "idle:10:bash\n");
+        state.idle_check_in_flight = true;
+        state.handle_idle_check_result(Some(2), b"", b"syntax error");
+        assert!(!state.idle_check_in_flight);
+        assert!(state.is_idle);
+        assert_eq!(state.shell_count, 1);
+        assert_eq!(state.empty_poll_streak, 0);
+    }
+
+    #[test]
+    fn consecutive_idle_polls_debounce_going_idle() {
+        let mut state = test_state();
+        state.consecutive_idle_polls = 3;
+        tick(&mut state, "active:10:cargo\n");
+        tick(&mut state, "idle:10:bash\n");
+        tick(&mut state, "idle:10:bash\n");
+        assert!(!state.is_idle);
+        assert_eq!(state.log_buffer.last().map(String::as_str), Some("idle poll 2/3, not idle yet"));
+
+        // A busy poll starts the streak over
+        tick(&mut state, "active:10:cargo\n");
+        tick(&mut state, "idle:10:bash\n");
+        tick(&mut state, "idle:10:bash\n");
+        assert!(!state.is_idle);
+        tick(&mut state, "idle:10:bash\n");
+        assert!(state.is_idle);
+        assert_eq!(state.idle_elapsed_secs, 15.0);
+
+        // So does input
+        state.update(Event::InputReceived);
+        assert_eq!(state.idle_poll_streak, 0);
+        tick(&mut state, "idle:10:bash\n");
+        tick(&mut state, "idle:10:bash\n");
+        assert!(!state.is_idle);
+        tick(&mut state, "idle:10:bash\n");
+        assert!(state.is_idle);
+    }
+
+    #[test]
+    fn parse_skips_malformed_lines() {
+        let mut state = test_state();
+        tick(&mut state, "active:10\nactive\n:\nidle:11:bash\n");
+        assert!(state.is_idle);
+        assert_eq!(state.shell_count, 1);
+        assert_eq!(state.active_pane_count, 0);
+    }
+
+    #[test]
+    fn parse_box_signals_keep_awake_without_counting_as_panes() {
+        let mut state = test_state();
+        tick(&mut state, "idle:10:bash\nactive:-:ssh-session\n");
+        assert!(!state.is_idle);
+        assert_eq!(state.active_pane_count, 0);
+        assert_eq!(state.shell_count, 1);
+        assert_eq!(state.active_processes, ["ssh-session"]);
+    }
+
+    #[test]
+    fn agent_cpu_comes_from_the_tick_delta_between_polls() {
+        let mut state = test_state();
+        state.update_agent_cpu("fgtree:100:500 0\n");
+        assert!(state.agent_ticks.is_empty());
+
+        state.claude_working_cpu_threshold = 10.0;
+        assert_eq!(dump_arg(&state, 14), "true");
+        state.update_agent_cpu("clktck:-:100\nfgtree:100:500 0\nfgtree:101:900 0\n");
+        assert!(state.agent_cpu_pct.is_empty());
+
+        // 10s ago, the first pane's tree had used 2s less CPU; the second one's foreground
+        // changed since, so it has fewer ticks
+        let then = unix_now() - 10.0;
+        state.agent_ticks = BTreeMap::from([("100".to_string(), (then, 300)), ("101".to_string(), (then, 950))]);
+        state.update_agent_cpu("clktck:-:100\nfgtree:100:500 0\nfgtree:101:900 0\n");
+        let pct = state.agent_cpu_pct["100"];
+        assert!((19.0..=20.0).contains(&pct), "{}", pct);
+        assert!(!state.agent_cpu_pct.contains_key("101"));
+    }
+
+    #[test]
+    fn container_busy_needs_one_container_over_the_threshold() {
+        let mut state = test_state();
+        let dump = "containerengines:-:/usr/bin/docker\ncontainercpu:-:docker/build 42.10%\ncontainercpu:-:docker/db 0.50%\n";
+        assert!(!state.container_busy(dump));
+
+        state.detect_containers = true;
+        state.container_cpu_threshold_pct = DEFAULT_CONTAINER_CPU_THRESHOLD_PCT;
+        assert!(state.container_busy(dump));
+        assert_eq!(state.container_engines.as_deref(), Some("/usr/bin/docker"));
+        assert!(!state.container_busy("containercpu:-:docker/db 0.50%\n"));
+        assert!(!state.container_busy("containerengines:-:none\n"));
+
+        // A hung daemon might be busy; one that isn't running has no containers
+        assert!(state.container_busy("containererror:-:docker 124\n"));
+        assert!(!state.container_busy("containererror:-:docker 1\n"));
+        assert_eq!(state.container_error.as_deref(), Some("docker 1"));
+        assert!(!state.container_busy(""));
+        assert_eq!(state.container_error, None);
+    }
+
+    #[test]
+    fn custom_idle_check_drives_the_state_machine() {
+        let mut state = test_state();
+        state.custom_idle_check = "my-check".to_string();
+        assert_eq!(state.idle_check_command(), ["bash", "-c", "my-check", "_", "1234"]);
+
+        timer_tick(&mut state, "idle:1:no lockfile\nwhatever\n");
+        timer_tick(&mut state, "idle:1:no lockfile\nwhatever\n");
+        assert!(state.is_idle);
+        assert_eq!(state.custom_idle_check_malformed, ["whatever"]);
+
+        timer_tick(&mut state, "active:1:lockfile\n");
+        assert!(!state.is_idle);
+        assert!(state.custom_idle_check_malformed.is_empty());
+    }
+
+    #[test]
+    fn too_many_panes_skips_the_scan_and_stays_active() {
+        const IDLE_SHELL: &str = "stat:100:100 (bash) S 1234 100 100 34816 100 4194560\n";
+        let mut state = test_state();
+        assert_eq!(dump_arg(&state, 13), "");
+        state.max_panes_for_detection = 100;
+        assert_eq!(dump_arg(&state, 13), "100");
+
+        timer_tick(&mut state, IDLE_SHELL);
+        timer_tick(&mut state, IDLE_SHELL);
+        assert!(state.is_idle);
+        timer_tick(&mut state, "toomanypanes:-:250\n");
+        assert!(state.too_many_panes);
+        assert!(!state.is_idle);
+        assert_eq!(state.idle_elapsed_secs, 0.0);
+
+        timer_tick(&mut state, IDLE_SHELL);
+        assert!(!state.too_many_panes);
+        assert_eq!(state.idle_elapsed_secs, 5.0);
+    }
+
+    #[test]
+    fn child_pids_are_reused_until_stale_or_gone() {
+        let mut state = test_state();
+        state.child_pid_refresh_polls = 2;
+        // Linux has nothing to cache
+        state.update_child_pids("children:-:10 11\n");
+        assert_eq!(state.child_pids, None);
+        state.platform_macos = true;
+        assert_eq!(dump_arg(&state, 11), "");
+
+        let stats = "stat:10:10 (bash) S 1234\nstat:11:11 (bash) S 1234\n";
+        state.update_child_pids(&format!("children:-:10 11\n{}", stats));
+        assert_eq!(dump_arg(&state, 11), "10 11");
+        state.update_child_pids(stats);
+        assert_eq!(dump_arg(&state, 11), "10 11");
+        state.update_child_pids(stats);
+        assert_eq!(dump_arg(&state, 11), "");
+        assert_eq!((state.child_pid_lookups, state.child_pid_reuses), (1, 2));
+
+        // A shell exited, so the next dump looks them up again
+        state.update_child_pids("children:-:10 11\n");
+        state.update_child_pids("stat:10:10 (bash) S 1234\n");
+        assert_eq!(state.child_pids, None);
+        assert_eq!(dump_arg(&state, 11), "");
+    }
+
+    #[test]
+    fn desktop_input_since_the_last_poll_is_activity() {
+        let mut state = test_state();
+        assert!(state.desktop_active("sysidle:-:2\n"));
+        assert!(!state.desktop_active("sysidle:-:30\n"));
+        assert!(!state.desktop_active("sysidle:-:unknown\n"));
+        assert!(state.system_idle_missing_logged);
+        assert!(!state.desktop_active("stat:1:x\n"));
+    }
+
+    #[test]
+    fn tab_filters_exclude_panes_by_tab_name() {
+        let mut state = test_state();
+        state.tab_names = BTreeMap::from([(0, "work".to_string()), (1, "monitor".to_string())]);
+        state.pane_tabs = BTreeMap::from([(0, 0), (1, 1), (2, 1), (3, 2)]);
+        assert!(state.excluded_panes().is_empty());
+
+        state.ignore_tabs = vec!["monitor".to_string()];
+        assert_eq!(state.excluded_panes(), [1, 2]);
+
+        // Pane 3's tab hasn't been reported, so it still counts
+        state.ignore_tabs.clear();
+        state.count_tabs = vec!["work".to_string()];
+        assert_eq!(state.excluded_panes(), [1, 2]);
     }
 }
@@ -0,0 +1,142 @@
+// Idle/suspend counters saved across plugin reloads, as simple `key=value` lines.
+
+/// The subset of plugin state that survives a reload.
+#[derive(Debug, Default, PartialEq)]
+pub struct PersistedState {
+    pub saved_at_unix: f64,
+    pub poll_count: u64,
+    pub clock_secs: f64,
+    pub last_activity_secs: f64,
+    pub is_idle: bool,
+    pub idle_elapsed_secs: f64,
+    pub countdown_active: bool,
+    pub countdown_remaining: f64,
+    pub session_start_unix: f64,
+    pub maxtime_countdown: bool,
+    pub session_extended: bool,
+    /// warn_first_cycle has shown its one WOULD SUSPEND countdown for this session
+    pub first_cycle_warned: bool,
+}
+
+impl PersistedState {
+    pub fn serialize(&self) -> String {
+        format!(
+            "saved_at_unix={}\npoll_count={}\nclock_secs={}\nlast_activity_secs={}\nis_idle={}\nidle_elapsed_secs={}\ncountdown_active={}\ncountdown_remaining={}\nsession_start_unix={}\nmaxtime_countdown={}\nsession_extended={}\nfirst_cycle_warned={}\n",
+            self.saved_at_unix,
+            self.poll_count,
+            self.clock_secs,
+            self.last_activity_secs,
+            self.is_idle,
+            self.idle_elapsed_secs,
+            self.countdown_active,
+            self.countdown_remaining,
+            self.session_start_unix,
+            self.maxtime_countdown,
+            self.session_extended,
+            self.first_cycle_warned,
+        )
+    }
+
+    /// Parses the output of `serialize`. Unknown keys are ignored so older plugin
+    /// versions can read files written by newer ones; a missing or bad timestamp
+    /// makes the whole file unusable.
+    pub fn parse(contents: &str) -> Option<Self> {
+        let mut state = PersistedState::default();
+        let mut has_timestamp = false;
+        for line in contents.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim() {
+                "saved_at_unix" => {
+                    state.saved_at_unix = value.parse().ok()?;
+                    has_timestamp = true;
+                }
+                "poll_count" => state.poll_count = value.parse().ok()?,
+                "clock_secs" => state.clock_secs = value.parse().ok()?,
+                "last_activity_secs" => state.last_activity_secs = value.parse().ok()?,
+                "is_idle" => state.is_idle = value == "true",
+                "idle_elapsed_secs" => state.idle_elapsed_secs = value.parse().ok()?,
+                "countdown_active" => state.countdown_active = value == "true",
+                "countdown_remaining" => state.countdown_remaining = value.parse().ok()?,
+                "session_start_unix" => state.session_start_unix = value.parse().ok()?,
+                "maxtime_countdown" => state.maxtime_countdown = value == "true",
+                "session_extended" => state.session_extended = value == "true",
+                "first_cycle_warned" => state.first_cycle_warned = value == "true",
+                _ => {}
+            }
+        }
+        has_timestamp.then_some(state)
+    }
+
+    /// Whether the state was saved no more than `max_age_secs` before `now_unix`. A save
+    /// time in the future (clock change) counts as stale too.
+    pub fn is_fresh(&self, now_unix: f64, max_age_secs: f64) -> bool {
+        (0.0..=max_age_secs).contains(&(now_unix - self.saved_at_unix))
+    }
+}
+
+/// Path of the state file for a given zellij server, as seen from inside the plugin
+/// sandbox (zellij maps the plugin's /tmp to its own temp directory).
+pub fn state_file_path(zellij_pid: u32) -> String {
+    format!("/tmp/zellij-idle-{}.state", zellij_pid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> PersistedState {
+        PersistedState {
+            saved_at_unix: 1_700_000_000.5,
+            poll_count: 42,
+            clock_secs: 210.0,
+            last_activity_secs: 150.0,
+            is_idle: true,
+            idle_elapsed_secs: 60.0,
+            countdown_active: true,
+            countdown_remaining: 12.5,
+            session_start_unix: 1_699_990_000.0,
+            maxtime_countdown: false,
+            session_extended: true,
+            first_cycle_warned: true,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let state = sample();
+        assert_eq!(PersistedState::parse(&state.serialize()), Some(state));
+    }
+
+    #[test]
+    fn unknown_keys_are_ignored() {
+        let contents = format!(
+            "{}from_a_newer_version=1\nnot a key value line\n",
+            sample().serialize()
+        );
+        assert_eq!(PersistedState::parse(&contents), Some(sample()));
+    }
+
+    #[test]
+    fn missing_or_bad_timestamp_is_unusable() {
+        assert_eq!(PersistedState::parse("poll_count=3\n"), None);
+        assert_eq!(
+            PersistedState::parse("saved_at_unix=yesterday\npoll_count=3\n"),
+            None
+        );
+        assert_eq!(
+            PersistedState::parse("saved_at_unix=1\npoll_count=many\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn freshness() {
+        let state = sample();
+        assert!(state.is_fresh(state.saved_at_unix + 4.0, 5.0));
+        assert!(!state.is_fresh(state.saved_at_unix + 6.0, 5.0));
+        assert!(!state.is_fresh(state.saved_at_unix - 1.0, 5.0));
+    }
+}
@@ -0,0 +1,167 @@
+// Running tally of suspends and time spent suspended, kept across reboots in a
+// `key=value` file next to the log, for estimating what the plugin has saved.
+
+/// How far back `recent` reaches, for "suspended N times this week".
+pub const RECENT_WINDOW_SECS: f64 = 7.0 * 24.0 * 3600.0;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Savings {
+    pub suspends: u64,
+    pub suspended_secs: f64,
+    /// When the suspend now under way was sent, or 0 when the box isn't suspended
+    pub suspended_at_unix: f64,
+    /// Times of the suspends within RECENT_WINDOW_SECS, oldest first
+    pub recent: Vec<f64>,
+}
+
+impl Savings {
+    pub fn serialize(&self) -> String {
+        format!(
+            "suspends={}\nsuspended_secs={}\nsuspended_at_unix={}\nrecent={}\n",
+            self.suspends,
+            self.suspended_secs,
+            self.suspended_at_unix,
+            self.recent
+                .iter()
+                .map(|t| t.to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+        )
+    }
+
+    /// Parses the output of `serialize`. Unlike the state file, a partly bad counter
+    /// file is still worth keeping, so values that don't parse are skipped.
+    pub fn parse(contents: &str) -> Self {
+        let mut savings = Savings::default();
+        for line in contents.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim() {
+                "suspends" => savings.suspends = value.parse().unwrap_or(0),
+                "suspended_secs" => savings.suspended_secs = value.parse().unwrap_or(0.0),
+                "suspended_at_unix" => savings.suspended_at_unix = value.parse().unwrap_or(0.0),
+                "recent" => {
+                    savings.recent = value.split(',').filter_map(|t| t.parse().ok()).collect()
+                }
+                _ => {}
+            }
+        }
+        savings
+    }
+
+    /// Counts a suspend sent at `now_unix`.
+    pub fn record_suspend(&mut self, now_unix: f64) {
+        self.suspends += 1;
+        self.suspended_at_unix = now_unix;
+        self.recent.push(now_unix);
+        self.recent.retain(|t| now_unix - t <= RECENT_WINDOW_SECS);
+    }
+
+    /// Takes back the suspend counted last, for one that never happened.
+    pub fn cancel_suspend(&mut self) {
+        if self.suspended_at_unix > 0.0 {
+            self.suspends = self.suspends.saturating_sub(1);
+            self.recent.pop();
+            self.suspended_at_unix = 0.0;
+        }
+    }
+
+    /// Ends the suspend under way at `resumed_unix` and returns how long it lasted.
+    pub fn record_resume(&mut self, resumed_unix: f64) -> Option<f64> {
+        if self.suspended_at_unix <= 0.0 {
+            return None;
+        }
+        let secs = (resumed_unix - self.suspended_at_unix).max(0.0);
+        self.suspended_secs += secs;
+        self.suspended_at_unix = 0.0;
+        Some(secs)
+    }
+
+    /// Adds counts kept in memory before the file was read, so a suspend recorded in
+    /// the meantime isn't lost when the file replaces them.
+    pub fn merge(&mut self, pending: Savings) {
+        self.suspends += pending.suspends;
+        self.suspended_secs += pending.suspended_secs;
+        if pending.suspended_at_unix > 0.0 {
+            self.suspended_at_unix = pending.suspended_at_unix;
+        }
+        self.recent.extend(pending.recent);
+    }
+
+    /// Suspends within RECENT_WINDOW_SECS of `now_unix`.
+    pub fn recent_suspends(&self, now_unix: f64) -> usize {
+        self.recent
+            .iter()
+            .filter(|t| now_unix - **t <= RECENT_WINDOW_SECS)
+            .count()
+    }
+
+    /// Estimated money saved at `hourly_cost` per hour of suspended time.
+    pub fn estimated_saved(&self, hourly_cost: f64) -> f64 {
+        self.suspended_secs / 3600.0 * hourly_cost
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut savings = Savings::default();
+        savings.record_suspend(1_000.0);
+        savings.record_resume(4_600.0);
+        savings.record_suspend(5_000.5);
+        assert_eq!(Savings::parse(&savings.serialize()), savings);
+    }
+
+    #[test]
+    fn bad_values_are_skipped() {
+        let savings = Savings::parse("suspends=3\nsuspended_secs=lots\nrecent=1,x,2\nnoise\n");
+        assert_eq!(savings.suspends, 3);
+        assert_eq!(savings.suspended_secs, 0.0);
+        assert_eq!(savings.recent, [1.0, 2.0]);
+    }
+
+    #[test]
+    fn suspend_and_resume_accumulate() {
+        let mut savings = Savings::default();
+        assert_eq!(savings.record_resume(10.0), None);
+
+        savings.record_suspend(1_000.0);
+        assert_eq!(savings.record_resume(8_200.0), Some(7_200.0));
+        assert_eq!(savings.estimated_saved(1.5), 3.0);
+
+        savings.record_suspend(9_000.0);
+        savings.cancel_suspend();
+        assert_eq!(savings.suspends, 1);
+        assert_eq!(savings.record_resume(9_500.0), None);
+    }
+
+    #[test]
+    fn merge_keeps_a_suspend_recorded_before_the_file_was_read() {
+        let mut pending = Savings::default();
+        pending.record_suspend(9_000.0);
+        let mut savings = Savings::parse("suspends=2\nsuspended_secs=60\nrecent=1000,2000\n");
+        savings.merge(pending);
+        assert_eq!(savings.suspends, 3);
+        assert_eq!(savings.suspended_secs, 60.0);
+        assert_eq!(savings.suspended_at_unix, 9_000.0);
+        assert_eq!(savings.recent, [1_000.0, 2_000.0, 9_000.0]);
+    }
+
+    #[test]
+    fn recent_suspends_cover_the_last_week() {
+        let mut savings = Savings::default();
+        savings.record_suspend(0.0);
+        savings.record_suspend(RECENT_WINDOW_SECS);
+        assert_eq!(savings.recent_suspends(RECENT_WINDOW_SECS), 2);
+        assert_eq!(savings.recent_suspends(RECENT_WINDOW_SECS + 1.0), 1);
+
+        savings.record_suspend(RECENT_WINDOW_SECS + 10.0);
+        assert_eq!(savings.recent, [RECENT_WINDOW_SECS, RECENT_WINDOW_SECS + 10.0]);
+        assert_eq!(savings.suspends, 3);
+    }
+}
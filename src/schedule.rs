@@ -0,0 +1,198 @@
+// Idle timeout profiles: `timeout_schedule` rules that pick the idle timeout by
+// weekday and local time, e.g. `mon-fri 09:00-18:00=1800; *=300`.
+
+use crate::{in_window, parse_duration, parse_hhmm};
+
+const WEEKDAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+/// One `<days> [HH:MM-HH:MM]=<secs>` rule; the timeout may also be a duration like `30m`.
+#[derive(Debug, PartialEq)]
+pub struct TimeoutRule {
+    /// The rule as written, minus the timeout, e.g. `mon-fri 09:00-18:00`
+    pub label: String,
+    /// Indexed Monday = 0 through Sunday = 6
+    days: [bool; 7],
+    /// Minutes since midnight, start..end; wraps past midnight when end < start
+    window: Option<(u32, u32)>,
+    pub timeout_secs: f64,
+}
+
+impl TimeoutRule {
+    /// Whether the rule covers `weekday` (Monday = 0) at `minutes` since midnight. A
+    /// rule that depends on a day or time never matches while those are unknown.
+    pub fn matches(&self, weekday: Option<usize>, minutes: Option<u32>) -> bool {
+        let day_ok = if self.days.iter().all(|d| *d) {
+            true
+        } else {
+            weekday.is_some_and(|d| self.days.get(d).copied().unwrap_or(false))
+        };
+        let time_ok = match self.window {
+            None => true,
+            Some((start, end)) => minutes.is_some_and(|now| in_window(now, start, end)),
+        };
+        day_ok && time_ok
+    }
+}
+
+/// Parses `;`-separated rules. Days are a comma-separated list of names or ranges
+/// (`mon-fri`, `sat,sun`, `fri-mon`), or `*` for every day, which is also the default
+/// when only a time range is given.
+pub fn parse_timeout_schedule(spec: &str) -> Result<Vec<TimeoutRule>, String> {
+    spec.split(';')
+        .map(str::trim)
+        .filter(|r| !r.is_empty())
+        .map(parse_rule)
+        .collect()
+}
+
+fn parse_rule(rule: &str) -> Result<TimeoutRule, String> {
+    let (when, secs) = rule
+        .rsplit_once('=')
+        .ok_or_else(|| format!("{:?}: expected <days> [HH:MM-HH:MM]=<secs>", rule))?;
+    let timeout_secs = parse_duration(secs)
+        .ok_or_else(|| format!("{:?}: bad timeout {:?}", rule, secs.trim()))?;
+
+    let mut days = None;
+    let mut window = None;
+    for token in when.split_whitespace() {
+        if token.contains(':') {
+            let (start, end) = token
+                .split_once('-')
+                .and_then(|(s, e)| Some((parse_hhmm(s)?, parse_hhmm(e)?)))
+                .ok_or_else(|| format!("{:?}: bad time range {:?}", rule, token))?;
+            window = Some((start, end));
+        } else {
+            days =
+                Some(parse_days(token).ok_or_else(|| format!("{:?}: bad days {:?}", rule, token))?);
+        }
+    }
+
+    Ok(TimeoutRule {
+        label: when.split_whitespace().collect::<Vec<_>>().join(" "),
+        days: days.unwrap_or([true; 7]),
+        window,
+        timeout_secs,
+    })
+}
+
+fn parse_days(token: &str) -> Option<[bool; 7]> {
+    if token == "*" {
+        return Some([true; 7]);
+    }
+    let day = |name: &str| {
+        let name = name.trim().to_ascii_lowercase();
+        WEEKDAYS.iter().position(|d| *d == name)
+    };
+    let mut days = [false; 7];
+    for part in token.split(',') {
+        match part.split_once('-') {
+            Some((from, to)) => {
+                let (from, to) = (day(from)?, day(to)?);
+                // Ranges can wrap around the end of the week, e.g. fri-mon
+                let mut d = from;
+                loop {
+                    days[d] = true;
+                    if d == to {
+                        break;
+                    }
+                    d = (d + 1) % 7;
+                }
+            }
+            None => days[day(part)?] = true,
+        }
+    }
+    Some(days)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MON: usize = 0;
+    const FRI: usize = 4;
+    const SAT: usize = 5;
+    const SUN: usize = 6;
+
+    fn at(h: u32, m: u32) -> Option<u32> {
+        Some(h * 60 + m)
+    }
+
+    /// The timeout of the first rule matching, as the plugin picks it.
+    fn pick(rules: &[TimeoutRule], weekday: usize, minutes: Option<u32>) -> Option<f64> {
+        rules
+            .iter()
+            .find(|r| r.matches(Some(weekday), minutes))
+            .map(|r| r.timeout_secs)
+    }
+
+    #[test]
+    fn parses_rules_and_labels() {
+        let rules =
+            parse_timeout_schedule(" mon-fri  09:00-18:00 = 1800 ;sat,sun=900; *=300;").unwrap();
+        let labels: Vec<&str> = rules.iter().map(|r| r.label.as_str()).collect();
+        assert_eq!(labels, ["mon-fri 09:00-18:00", "sat,sun", "*"]);
+        assert_eq!(rules[0].timeout_secs, 1800.0);
+        assert_eq!(parse_timeout_schedule("").unwrap(), Vec::new());
+        assert_eq!(parse_timeout_schedule("*=30m").unwrap()[0].timeout_secs, 1800.0);
+    }
+
+    #[test]
+    fn first_match_wins() {
+        let rules = parse_timeout_schedule("mon-fri 09:00-18:00=1800; sat,sun=900; *=300").unwrap();
+        assert_eq!(pick(&rules, MON, at(10, 0)), Some(1800.0));
+        assert_eq!(pick(&rules, MON, at(18, 0)), Some(300.0));
+        assert_eq!(pick(&rules, SAT, at(10, 0)), Some(900.0));
+        assert_eq!(pick(&rules, SUN, at(23, 59)), Some(900.0));
+    }
+
+    #[test]
+    fn day_ranges_wrap_around_the_week() {
+        let rules = parse_timeout_schedule("fri-mon=60").unwrap();
+        for (day, expected) in [
+            (FRI, true),
+            (SAT, true),
+            (SUN, true),
+            (MON, true),
+            (1, false),
+        ] {
+            assert_eq!(rules[0].matches(Some(day), None), expected, "day {}", day);
+        }
+    }
+
+    #[test]
+    fn time_windows_wrap_past_midnight() {
+        let rules = parse_timeout_schedule("22:00-06:00=60").unwrap();
+        assert!(rules[0].matches(Some(MON), at(23, 0)));
+        assert!(rules[0].matches(Some(MON), at(5, 59)));
+        assert!(!rules[0].matches(Some(MON), at(6, 0)));
+        assert!(!rules[0].matches(Some(MON), at(12, 0)));
+    }
+
+    #[test]
+    fn unknown_day_or_time_only_matches_rules_that_dont_need_it() {
+        let rules = parse_timeout_schedule("mon=1; 09:00-17:00=2; *=3").unwrap();
+        assert!(!rules[0].matches(None, at(10, 0)));
+        assert!(!rules[1].matches(Some(MON), None));
+        assert!(rules[2].matches(None, None));
+    }
+
+    #[test]
+    fn errors_name_the_bad_part() {
+        assert_eq!(
+            parse_timeout_schedule("mon-fri"),
+            Err(r#""mon-fri": expected <days> [HH:MM-HH:MM]=<secs>"#.to_string())
+        );
+        assert_eq!(
+            parse_timeout_schedule("mon=soon"),
+            Err(r#""mon=soon": bad timeout "soon""#.to_string())
+        );
+        assert_eq!(
+            parse_timeout_schedule("*=1; funday=300"),
+            Err(r#""funday=300": bad days "funday""#.to_string())
+        );
+        assert_eq!(
+            parse_timeout_schedule("09:00-25:00=300"),
+            Err(r#""09:00-25:00=300": bad time range "09:00-25:00""#.to_string())
+        );
+    }
+}